@@ -0,0 +1,239 @@
+use std::sync::Arc;
+use chrono::{Duration, Utc};
+use log::error;
+use regex::Regex;
+use tokio::task;
+
+use crate::auth::TokenIssuer;
+use crate::domain::account_repository::AccountRepository;
+use crate::domain::repository::Repositories;
+use crate::domain::user_repository::UserRepository;
+use crate::error::Error;
+use crate::model::account::Account;
+use crate::model::user::User;
+use crate::password;
+use crate::proto::{AccountOutput, LoginInput, LoginOutput, OutputError, RegisterInput};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+const MAX_DISPLAY_NAME_LENGTH: usize = 48;
+const MAX_AVATAR_URL_LENGTH: usize = 512;
+const MAX_BIO_LENGTH: usize = 280;
+
+lazy_static! {
+  static ref USERNAME_REGEX: Regex = Regex::new("^[a-z0-9_]{3,24}$").unwrap();
+  // checked against when the username is unknown, so failed logins all take as long
+  static ref DUMMY_HASH: String = password::hash("");
+}
+
+/// Registration and login. Usernames are compared lowercase; the storage keeps them unique.
+pub struct AccountService {
+  account_repo: Arc<dyn AccountRepository>,
+  user_repo: Arc<dyn UserRepository>,
+  issuer: Arc<dyn TokenIssuer>,
+  token_ttl: Duration,
+}
+
+impl AccountService {
+  pub fn new(repositories: &Repositories, issuer: Arc<dyn TokenIssuer>, token_ttl: Duration) -> Self {
+    AccountService {
+      account_repo: repositories.account(),
+      user_repo: repositories.user(),
+      issuer,
+      token_ttl,
+    }
+  }
+
+  pub async fn register(&self, input: RegisterInput) -> Result<AccountOutput, OutputError> {
+    let username = input.username.trim().to_lowercase();
+    if !USERNAME_REGEX.is_match(&username) {
+      return Err(OutputError::InvalidUserName);
+    }
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&input.password.chars().count()) {
+      return Err(OutputError::InvalidPassword);
+    }
+
+    let display_name = trimmed(input.display_name).unwrap_or_else(|| username.clone());
+    let avatar_url = trimmed(input.avatar_url);
+    let bio = trimmed(input.bio);
+    if display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH
+      || !avatar_url.iter().all(|url| Self::is_valid_avatar_url(url))
+      || bio.as_ref().map_or(0, |bio| bio.chars().count()) > MAX_BIO_LENGTH {
+      return Err(OutputError::InvalidProfile);
+    }
+
+    if self.account_repo.load_by_username(&username).await.is_some() {
+      return Err(OutputError::UserNameTaken);
+    }
+
+    let password_hash = Self::hash_password(input.password).await?;
+    let account = Account {
+      avatar_url,
+      bio,
+      ..Account::new(&username, password_hash, &display_name)
+    };
+
+    // rooms know members by their user record; it is written first and given back when
+    // another registration takes the username meanwhile
+    let user_id = account.id;
+    let user = User::new(user_id, &account.display_name);
+    if self.user_repo.create_user(user).await.is_none() {
+      error!("creating the user record of {} failed", username);
+      return Err(OutputError::RegistrationFailed);
+    }
+
+    match self.account_repo.create_account(account).await {
+      Ok(account) => Ok(Self::account_output(&account)),
+      Err(err) => {
+        if let Err(release_err) = self.user_repo.delete_user(user_id).await {
+          error!("releasing the user record of {} failed: {}", username, release_err);
+        }
+        match err {
+          Error::Conflict(_) => Err(OutputError::UserNameTaken),
+          err => {
+            error!("registering {} failed: {}", username, err);
+            Err(OutputError::RegistrationFailed)
+          }
+        }
+      }
+    }
+  }
+
+  pub async fn login(&self, input: LoginInput) -> Result<LoginOutput, OutputError> {
+    let username = input.username.trim().to_lowercase();
+    let account = self.account_repo.load_by_username(&username).await;
+    let password_hash = account.as_ref().map(|account| account.password_hash.clone());
+    let account = match (Self::verify_password(input.password, password_hash).await, account) {
+      (true, Some(account)) => account,
+      _ => return Err(OutputError::InvalidCredentials),
+    };
+
+    let expires_at = Utc::now() + self.token_ttl;
+    let token = self.issuer.issue(account.id, expires_at);
+    Ok(LoginOutput::new(token, expires_at, Self::account_output(&account)))
+  }
+
+  // PBKDF2 is slow on purpose; it runs on the blocking pool so that logins cannot stall the sockets
+
+  async fn hash_password(password: String) -> Result<String, OutputError> {
+    task::spawn_blocking(move || password::hash(&password)).await.map_err(|err| {
+      error!("hashing a password failed: {}", err);
+      OutputError::RegistrationFailed
+    })
+  }
+
+  /// Checks against `DUMMY_HASH` when there is no hash, so unknown usernames take as long.
+  async fn verify_password(password: String, password_hash: Option<String>) -> bool {
+    let verifying = task::spawn_blocking(move || match password_hash {
+      Some(password_hash) => password::verify(&password, &password_hash),
+      None => {
+        password::verify(&password, &DUMMY_HASH);
+        false
+      },
+    });
+    verifying.await.unwrap_or_else(|err| {
+      error!("verifying a password failed: {}", err);
+      false
+    })
+  }
+
+  fn is_valid_avatar_url(url: &str) -> bool {
+    url.len() <= MAX_AVATAR_URL_LENGTH && (url.starts_with("https://") || url.starts_with("http://"))
+  }
+
+  fn account_output(account: &Account) -> AccountOutput {
+    AccountOutput {
+      id: account.id,
+      username: account.username.clone(),
+      display_name: account.display_name.clone(),
+      avatar_url: account.avatar_url.clone(),
+      bio: account.bio.clone(),
+      create_at: account.create_at,
+    }
+  }
+}
+
+fn trimmed(value: Option<String>) -> Option<String> {
+  value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::auth::{JwtVerifier, TokenVerifier};
+
+  macro_rules! aw {
+    ($e:expr) => {
+      tokio_test::block_on($e)
+    };
+  }
+
+  fn register_input(username: &str, password: &str) -> RegisterInput {
+    RegisterInput {
+      username: username.to_string(),
+      password: password.to_string(),
+      display_name: None,
+      avatar_url: None,
+      bio: None,
+    }
+  }
+
+  fn login(service: &AccountService, username: &str, password: &str) -> Result<LoginOutput, OutputError> {
+    aw!(service.login(LoginInput { username: username.to_string(), password: password.to_string() }))
+  }
+
+  #[test]
+  fn test001_register_then_login() {
+    let repositories = Repositories::in_memory();
+    let tokens = Arc::new(JwtVerifier::new("secret"));
+    let service = AccountService::new(&repositories, tokens.clone(), Duration::hours(1));
+
+    let input = RegisterInput { bio: Some(" hello ".to_string()), ..register_input(" Alice ", "correct horse") };
+    let account = aw!(service.register(input)).unwrap();
+    assert_eq!(account.username, "alice");
+    assert_eq!(account.display_name, "alice");
+    assert_eq!(account.bio, Some("hello".to_string()));
+    assert_eq!(aw!(repositories.user().load_one_user(account.id)).unwrap().name, "alice");
+
+    let stored = aw!(repositories.account().load_account(account.id)).unwrap();
+    assert!(!stored.password_hash.contains("correct horse"));
+
+    let logged_in = login(&service, "ALICE", "correct horse").unwrap();
+    assert_eq!(logged_in.account, account);
+    assert_eq!(tokens.verify(&logged_in.token), Ok(account.id));
+
+    assert_eq!(login(&service, "alice", "wrong horse"), Err(OutputError::InvalidCredentials));
+    assert_eq!(login(&service, "bob", "correct horse"), Err(OutputError::InvalidCredentials));
+  }
+
+  #[test]
+  fn test002_registration_is_validated() {
+    let service = AccountService::new(&Repositories::in_memory(), Arc::new(JwtVerifier::new("secret")), Duration::hours(1));
+    aw!(service.register(register_input("alice", "correct horse"))).unwrap();
+
+    assert_eq!(aw!(service.register(register_input("ALICE", "another horse"))), Err(OutputError::UserNameTaken));
+    assert_eq!(aw!(service.register(register_input("a!", "correct horse"))), Err(OutputError::InvalidUserName));
+    assert_eq!(aw!(service.register(register_input("bob", "short"))), Err(OutputError::InvalidPassword));
+
+    let input = RegisterInput { avatar_url: Some("javascript:alert(1)".to_string()), ..register_input("bob", "correct horse") };
+    assert_eq!(aw!(service.register(input)), Err(OutputError::InvalidProfile));
+  }
+
+  #[test]
+  fn test003_display_names_may_be_shared() {
+    let repositories = Repositories::in_memory();
+    let service = AccountService::new(&repositories, Arc::new(JwtVerifier::new("secret")), Duration::hours(1));
+    let named = |username: &str, display_name: &str| RegisterInput {
+      display_name: Some(display_name.to_string()),
+      ..register_input(username, "correct horse")
+    };
+    let alice = aw!(service.register(named("alice", "Alice"))).unwrap();
+    let other = aw!(service.register(named("alice2", "Alice"))).unwrap();
+    assert_eq!(aw!(repositories.user().load_one_user(other.id)).unwrap().name, "Alice");
+
+    // a taken username leaves no user record behind
+    assert_eq!(aw!(service.register(named("alice", "Someone"))), Err(OutputError::UserNameTaken));
+    assert_eq!(aw!(repositories.user().load_users(1, 10)).unwrap().len(), 2);
+    assert_eq!(aw!(repositories.user().load_one_user(alice.id)).unwrap().name, "Alice");
+  }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+const BEARER_PREFIX: &str = "Bearer ";
+const JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthError {
+  Missing,
+  Malformed,
+  BadSignature,
+  Expired,
+}
+
+impl fmt::Display for AuthError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AuthError::Missing => write!(f, "no token"),
+      AuthError::Malformed => write!(f, "malformed token"),
+      AuthError::BadSignature => write!(f, "bad token signature"),
+      AuthError::Expired => write!(f, "expired token"),
+    }
+  }
+}
+
+/// Tells who presented a bearer token. The servers ask it once per websocket upgrade and bind
+/// the answer to the connection.
+pub trait TokenVerifier: Send + Sync {
+  fn verify(&self, token: &str) -> Result<Uuid, AuthError>;
+}
+
+/// Signs the tokens handed out at login.
+pub trait TokenIssuer: Send + Sync {
+  fn issue(&self, user_id: Uuid, expires_at: DateTime<Utc>) -> String;
+}
+
+/// HS256 JSON web tokens naming the user in `sub`, valid until `exp` (seconds since epoch).
+pub struct JwtVerifier {
+  secret: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+  sub: Uuid,
+  exp: i64,
+}
+
+#[derive(Deserialize)]
+struct Header {
+  alg: String,
+}
+
+impl JwtVerifier {
+  pub fn new(secret: &str) -> Self {
+    JwtVerifier { secret: secret.as_bytes().to_vec() }
+  }
+
+  fn mac(&self) -> HmacSha256 {
+    // HMAC takes keys of any length
+    HmacSha256::new_varkey(&self.secret).unwrap()
+  }
+}
+
+impl TokenIssuer for JwtVerifier {
+  fn issue(&self, user_id: Uuid, expires_at: DateTime<Utc>) -> String {
+    let claims = Claims { sub: user_id, exp: expires_at.timestamp() };
+    let signed = format!("{}.{}", encode(JWT_HEADER.as_bytes()), encode(&serde_json::to_vec(&claims).unwrap()));
+    let mut mac = self.mac();
+    mac.update(signed.as_bytes());
+    format!("{}.{}", signed, encode(&mac.finalize().into_bytes()))
+  }
+}
+
+impl TokenVerifier for JwtVerifier {
+  fn verify(&self, token: &str) -> Result<Uuid, AuthError> {
+    let mut parts = token.rsplitn(2, '.');
+    let signature = parts.next().and_then(decode).ok_or(AuthError::Malformed)?;
+    let signed = parts.next().ok_or(AuthError::Malformed)?;
+    let mut parts = signed.splitn(2, '.');
+    let header = parts.next().ok_or(AuthError::Malformed)?;
+    let claims = parts.next().ok_or(AuthError::Malformed)?;
+
+    let header: Header = decode_json(header)?;
+    if header.alg != "HS256" {
+      return Err(AuthError::Malformed);
+    }
+
+    let mut mac = self.mac();
+    mac.update(signed.as_bytes());
+    mac.verify(&signature).map_err(|_| AuthError::BadSignature)?;
+
+    let claims: Claims = decode_json(claims)?;
+    if claims.exp <= Utc::now().timestamp() {
+      return Err(AuthError::Expired);
+    }
+    Ok(claims.sub)
+  }
+}
+
+/// Picks the token of an upgrade request: `Authorization: Bearer <token>`, or the `token` query
+/// parameter for browsers, which cannot set headers on websockets.
+pub fn bearer_token(authorization: Option<&str>, query_token: Option<&str>) -> Option<String> {
+  authorization
+    .and_then(|header| header.strip_prefix(BEARER_PREFIX))
+    .or(query_token)
+    .map(|token| token.trim().to_string())
+    .filter(|token| !token.is_empty())
+}
+
+pub fn authenticate(verifier: &dyn TokenVerifier, token: Option<String>) -> Result<Uuid, AuthError> {
+  verifier.verify(&token.ok_or(AuthError::Missing)?)
+}
+
+fn encode(data: &[u8]) -> String {
+  base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(data: &str) -> Option<Vec<u8>> {
+  base64::decode_config(data, base64::URL_SAFE_NO_PAD).ok()
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(data: &str) -> Result<T, AuthError> {
+  decode(data)
+    .and_then(|json| serde_json::from_slice(&json).ok())
+    .ok_or(AuthError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::Duration;
+
+  #[test]
+  fn test001_issued_tokens_verify_until_they_expire() {
+    let verifier = JwtVerifier::new("secret");
+    let user_id = Uuid::new_v4();
+
+    let token = verifier.issue(user_id, Utc::now() + Duration::minutes(5));
+    assert_eq!(verifier.verify(&token), Ok(user_id));
+    assert_eq!(JwtVerifier::new("other").verify(&token), Err(AuthError::BadSignature));
+
+    let expired = verifier.issue(user_id, Utc::now() - Duration::seconds(1));
+    assert_eq!(verifier.verify(&expired), Err(AuthError::Expired));
+  }
+
+  #[test]
+  fn test002_tampered_tokens_are_refused() {
+    let verifier = JwtVerifier::new("secret");
+    let token = verifier.issue(Uuid::new_v4(), Utc::now() + Duration::minutes(5));
+    let parts: Vec<&str> = token.split('.').collect();
+
+    let claims = Claims { sub: Uuid::new_v4(), exp: Utc::now().timestamp() + 300 };
+    let forged = format!("{}.{}.{}", parts[0], encode(&serde_json::to_vec(&claims).unwrap()), parts[2]);
+    assert_eq!(verifier.verify(&forged), Err(AuthError::BadSignature));
+
+    let unsigned = format!("{}.{}.", encode(br#"{"alg":"none"}"#), parts[1]);
+    assert_eq!(verifier.verify(&unsigned), Err(AuthError::Malformed));
+    assert_eq!(verifier.verify("garbage"), Err(AuthError::Malformed));
+    assert_eq!(authenticate(&verifier, None), Err(AuthError::Missing));
+  }
+
+  #[test]
+  fn test003_header_token_wins_over_query() {
+    assert_eq!(bearer_token(Some("Bearer abc"), Some("def")), Some("abc".to_string()));
+    assert_eq!(bearer_token(Some("Basic abc"), Some("def")), Some("def".to_string()));
+    assert_eq!(bearer_token(None, Some(" ")), None);
+  }
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use cassandra_cpp::*;
+use log::error;
+use uuid::Uuid;
+
+use crate::cass::repository::Utils;
+use crate::cass::statement_cache::StatementCache;
+use crate::domain::account_repository::AccountRepository;
+use crate::error;
+use crate::model::account::Account;
+
+pub struct CassAccountRepository {
+    pub(crate) statements: Arc<StatementCache>
+}
+
+impl CassAccountRepository {
+    // claims the username first; the lightweight transaction makes it unique across nodes
+    const CLAIM_USERNAME: &'static str = "INSERT INTO account_by_username (username, id) VALUES (?, ?) IF NOT EXISTS";
+
+    const INSERT_QUERY: &'static str = "INSERT INTO \
+    account (id, username, password_hash, display_name, avatar_url, bio, create_at) \
+    VALUES (?, ?, ?, ?, ?, ?, ?)";
+
+    // only ever releases the claim this account made
+    const RELEASE_USERNAME: &'static str = "DELETE FROM account_by_username WHERE username = ? IF id = ?";
+
+    const SELECT_ONE: &'static str = "SELECT id, username, password_hash, display_name, avatar_url, bio, create_at \
+    FROM account WHERE id = ?";
+
+    const SELECT_BY_USERNAME: &'static str = "SELECT id FROM account_by_username WHERE username = ?";
+
+    fn bind_to_account(row: Row) -> Option<Account> {
+        let id: cassandra_cpp::Uuid = Result::ok(row.get(0))?;
+        Some(
+            Account {
+                id: Utils::from_cass_uuid_to_uuid(id),
+                username: Result::ok(row.get(1))?,
+                password_hash: Result::ok(row.get(2))?,
+                display_name: Result::ok(row.get(3))?,
+                avatar_url: Result::ok(row.get(4)),
+                bio: Result::ok(row.get(5)),
+                create_at: Utils::from_timestamp_to_datetime(Result::ok(row.get(6))?),
+            }
+        )
+    }
+
+    async fn release_username(&self, account: &Account) -> error::Result<()> {
+        let mut statement = self.statements.statement(Self::RELEASE_USERNAME).await?;
+        statement.bind_string(0, account.username.as_str()).ok();
+        statement.bind_uuid(1, Utils::from_uuid_to_cass_uuid(account.id)).ok();
+        self.statements.execute(&statement).await?;
+        Ok(())
+    }
+
+    fn bind_optional(statement: &mut Statement, index: usize, value: &Option<String>) {
+        match value {
+            Some(value) => statement.bind_string(index, value.as_str()).ok(),
+            None => statement.bind_null(index).ok(),
+        };
+    }
+}
+
+#[async_trait]
+impl AccountRepository for CassAccountRepository {
+
+    async fn create_account(&self, account: Account) -> error::Result<Account> {
+        let mut statement = self.statements.statement(Self::CLAIM_USERNAME).await?;
+        statement.bind_string(0, account.username.as_str()).ok();
+        statement.bind_uuid(1, Utils::from_uuid_to_cass_uuid(account.id)).ok();
+
+        let result = self.statements.execute(&statement).await?;
+        let applied: bool = result.first_row()
+            .and_then(|row| Result::ok(row.get(0)))
+            .unwrap_or(false);
+        if !applied {
+            return Err(error::Error::Conflict(format!("username {} is taken", account.username)));
+        }
+
+        let mut statement = self.statements.statement(Self::INSERT_QUERY).await?;
+        statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid(account.id)).ok();
+        statement.bind_string(1, account.username.as_str()).ok();
+        statement.bind_string(2, account.password_hash.as_str()).ok();
+        statement.bind_string(3, account.display_name.as_str()).ok();
+        Self::bind_optional(&mut statement, 4, &account.avatar_url);
+        Self::bind_optional(&mut statement, 5, &account.bio);
+        statement.bind_int64(6, account.create_at.timestamp()).ok();
+
+        if let Err(err) = self.statements.execute(&statement).await {
+            // an account that was never written must not keep its username
+            if let Err(release_err) = self.release_username(&account).await {
+                error!("releasing username {} failed: {}", account.username, release_err);
+            }
+            return Err(err.into());
+        }
+        Ok(account)
+    }
+
+    async fn load_account(&self, id: Uuid) -> Option<Account> {
+        let mut statement = self.statements.statement(Self::SELECT_ONE).await.ok()?;
+        statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid(id)).ok();
+
+        let result = self.statements.execute(&statement).await.ok()?;
+        result.first_row().and_then(Self::bind_to_account)
+    }
+
+    async fn load_by_username(&self, username: &str) -> Option<Account> {
+        let mut statement = self.statements.statement(Self::SELECT_BY_USERNAME).await.ok()?;
+        statement.bind_string(0, username).ok();
+
+        let result = self.statements.execute(&statement).await.ok()?;
+        let id: cassandra_cpp::Uuid = result.first_row().and_then(|row| Result::ok(row.get(0)))?;
+        self.load_account(Utils::from_cass_uuid_to_uuid(id)).await
+    }
+}
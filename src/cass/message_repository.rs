@@ -0,0 +1,275 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use cassandra_cpp::*;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use uuid::Uuid;
+
+use crate::model::user::User;
+use crate::model::message::Message;
+use crate::cass::repository::Utils;
+use crate::cass::statement_cache::StatementCache;
+use crate::domain::message_repository::MessageRepository;
+use crate::error;
+
+pub struct CassMessageRepository {
+    pub(crate) statements: Arc<StatementCache>
+}
+
+impl CassMessageRepository {
+    const INSERT_QUERY: &'static str = "INSERT INTO room_message \
+    (room_id, bucket, id, from_id, from_name, body, created_at) \
+    VALUES \
+    (?, ?, ?, ?, ?, ?, ?)";
+
+    const INSERT_BUCKET_QUERY: &'static str = "INSERT INTO room_message_bucket (room_id, bucket) VALUES (?, ?)";
+
+    const UPDATE_BODY_QUERY: &'static str = "UPDATE room_message SET body = ?, edited_at = ? WHERE \
+      room_id = ? AND \
+      bucket = ? AND \
+      id = ?";
+
+    const SELECT_BUCKETS_QUERY: &'static str = "SELECT bucket FROM room_message_bucket WHERE room_id = ?";
+
+    const SELECT_ALL_BY_BUCKET_QUERY: &'static str = "\
+    SELECT id, from_id, from_name, room_id, body, created_at, toTimestamp(id), edited_at \
+    FROM room_message \
+    WHERE room_id = ? \
+      AND bucket = ? \
+    LIMIT ?";
+
+    const SELECT_BEFORE_BY_BUCKET_QUERY: &'static str = "\
+    SELECT id, from_id, from_name, room_id, body, created_at, toTimestamp(id), edited_at \
+    FROM room_message \
+    WHERE room_id = ? \
+      AND bucket = ? \
+      AND id < ? \
+    LIMIT ?";
+
+    const SELECT_ONE_QUERY: &'static str = "\
+    SELECT id, from_id, from_name, room_id, body, created_at, toTimestamp(id), edited_at \
+    FROM room_message \
+    WHERE room_id = ? AND bucket = ? AND id = ?";
+
+    const SELECT_EXIST_QUERY: &'static str = "SELECT COUNT(*) FROM room_message WHERE room_id = ? AND bucket = ? AND id = ?";
+
+    const DELETE_QUERY: &'static str = "DELETE FROM room_message WHERE \
+      room_id = ? AND \
+      bucket = ? AND \
+      id = ?";
+
+    const SECONDS_PER_BUCKET: u64 = 24 * 60 * 60;
+
+    /// Messages of a room are partitioned by the day of their TIMEUUID, so a message
+    /// is found again from its id alone.
+    pub(crate) fn bucket_of(msg_id: Uuid) -> i32 {
+        msg_id.to_timestamp()
+            .map(|timestamp| (timestamp.to_unix().0 / Self::SECONDS_PER_BUCKET) as i32)
+            .unwrap_or(0)
+    }
+
+    async fn statement(&self, query: &'static str) -> Option<Statement> {
+        match self.statements.statement(query).await {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                error!("preparing {:?} failed: {}", query, error);
+                None
+            }
+        }
+    }
+
+    async fn message_statement(&self, query: &'static str, room_id: &str, msg_id: Uuid, offset: usize) -> Option<Statement> {
+        let mut statement = self.statement(query).await?;
+        statement.bind_string(offset, room_id).ok();
+        statement.bind_int32(offset + 1, Self::bucket_of(msg_id)).ok();
+        statement.bind_uuid(offset + 2, Utils::from_uuid_to_cass_uuid(msg_id)).ok();
+        Some(statement)
+    }
+
+    async fn load_buckets(&self, room_id: &str) -> Option<Vec<i32>> {
+        let mut statement = self.statement(Self::SELECT_BUCKETS_QUERY).await?;
+        statement.bind_string(0, room_id).ok();
+
+        match self.statements.execute(&statement).await {
+            Ok(result) => Some(result.iter().filter_map(|row| Result::ok( row.get(0) )).collect()),
+            Err(error) => {
+                error!("loading message buckets of room {} failed: {}", room_id, error);
+                None
+            }
+        }
+    }
+
+    /// The message of a row, or `None` when a column it needs is null or unreadable.
+    fn bind_to_message(row: Row) -> Option<Message> {
+        let msg_id: cassandra_cpp::Uuid = Result::ok( row.get(0) )?;
+        let from_id: cassandra_cpp::Uuid = Result::ok( row.get(1) )?;
+
+        // rows written before created_at existed fall back to the time of their id
+        let created_at: i64 = Result::ok( row.get(5) )
+            .or_else(|| Result::ok( row.get(6) ))?;
+        Some(
+            Message {
+                id: Utils::from_cass_uuid_to_uuid(msg_id),
+                from: User {
+                    id: Utils::from_cass_uuid_to_uuid(from_id),
+                    name: Result::ok( row.get(2) )?,
+                },
+                room_id: Result::ok( row.get(3) )?,
+                body: Result::ok( row.get(4) )?,
+                created_at: Utils::from_millis_to_datetime(created_at),
+                edited_at: Result::ok( row.get(7) ).map(Utils::from_millis_to_datetime),
+            }
+        )
+    }
+}
+
+#[async_trait]
+impl MessageRepository for CassMessageRepository {
+
+    async fn add_new_message(&self, room_id: &str, message: Message) -> Option<Message> {
+        let persistent_msg = message.clone();
+        let bucket = Self::bucket_of(persistent_msg.id);
+
+        let mut bucket_statement = self.statement(Self::INSERT_BUCKET_QUERY).await?;
+        bucket_statement.bind_string(0, room_id).ok();
+        bucket_statement.bind_int32(1, bucket).ok();
+        if let Err(error) = self.statements.execute(&bucket_statement).await {
+            error!("adding message bucket of room {} failed: {}", room_id, error);
+            return None;
+        }
+
+        let mut statement = self.statement(Self::INSERT_QUERY).await?;
+        statement.bind_string(0, room_id).ok();
+        statement.bind_int32(1, bucket).ok();
+        statement.bind_uuid(2, Utils::from_uuid_to_cass_uuid(persistent_msg.id)).ok();
+        statement.bind_uuid(3, Utils::from_uuid_to_cass_uuid(persistent_msg.from.id)).ok();
+        statement.bind_string(4, persistent_msg.from.name.as_str()).ok();
+        statement.bind_string(5, persistent_msg.body.as_str()).ok();
+        statement.bind_int64(6, persistent_msg.created_at.timestamp_millis()).ok();
+
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Ok(_) => Some(message),
+            Err(error) => {
+                error!("adding message to room {} failed: {}", room_id, error);
+                None
+            }
+        }
+    }
+
+    async fn update_message_body(&self, room_id: &str, msg_id: Uuid, body: &str, edited_at: DateTime<Utc>) -> Option<Message> {
+        if !self.check_message_exists(room_id, msg_id).await {
+            return None;
+        }
+
+        let mut statement = self.message_statement(Self::UPDATE_BODY_QUERY, room_id, msg_id, 2).await?;
+        statement.bind_string(0, body).ok();
+        statement.bind_int64(1, edited_at.timestamp_millis()).ok();
+
+        match self.statements.execute(&statement).await {
+            Ok(_) => self.load_one_message(room_id, msg_id).await,
+            Err(error) => {
+                error!("editing message {} of room {} failed: {}", msg_id, room_id, error);
+                None
+            }
+        }
+    }
+
+    async fn load_messages_by_room(&self, room_id: &str, before: Option<Uuid>, limit: i32) -> Option<Vec<Message>> {
+        let mut res = Vec::<Message>::new();
+        let limit = limit.max(0) as usize;
+        let before_bucket = before.map(Self::bucket_of);
+
+        // walk the day buckets newest first, from the one holding the cursor, until the page is full
+        let buckets = self.load_buckets(room_id).await?
+            .into_iter()
+            .filter(|bucket| before_bucket.iter().all(|before_bucket| bucket <= before_bucket));
+        for bucket in buckets {
+            if res.len() >= limit {
+                break;
+            }
+
+            let mut statement = match before.filter(|_| before_bucket == Some(bucket)) {
+                Some(before) => {
+                    let mut statement = self.statement(Self::SELECT_BEFORE_BY_BUCKET_QUERY).await?;
+                    statement.bind_uuid(2, Utils::from_uuid_to_cass_uuid(before)).ok();
+                    statement.bind_int32(3, (limit - res.len()) as i32).ok();
+                    statement
+                },
+                None => {
+                    let mut statement = self.statement(Self::SELECT_ALL_BY_BUCKET_QUERY).await?;
+                    statement.bind_int32(2, (limit - res.len()) as i32).ok();
+                    statement
+                },
+            };
+            statement.bind_string(0, room_id).ok();
+            statement.bind_int32(1, bucket).ok();
+
+            match self.statements.execute(&statement).await {
+                Err(error) => {
+                    error!("loading messages of room {} failed: {}", room_id, error);
+                    break;
+                },
+                Ok(result) => {
+                    for row in result.iter() {
+                        match Self::bind_to_message(row) {
+                            Some(message) => res.push(message),
+                            None => warn!("skipping an unreadable message of room {}", room_id),
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(res)
+    }
+
+    async fn load_one_message(&self, room_id: &str, msg_id: Uuid) -> Option<Message> {
+        let statement = self.message_statement(Self::SELECT_ONE_QUERY, room_id, msg_id, 0).await?;
+
+        match self.statements.execute(&statement).await {
+            Ok(result) => {
+                let message = Self::bind_to_message(result.first_row()?);
+                if message.is_none() {
+                    warn!("message {} of room {} is unreadable", msg_id, room_id);
+                }
+                message
+            }
+            Err(error) => {
+                error!("loading message {} of room {} failed: {}", msg_id, room_id, error);
+                None
+            }
+        }
+    }
+
+    async fn check_message_exists(&self, room_id: &str, msg_id: Uuid) -> bool {
+        let statement = match self.message_statement(Self::SELECT_EXIST_QUERY, room_id, msg_id, 0).await {
+            Some(statement) => statement,
+            None => return false,
+        };
+        match self.statements.execute(&statement).await {
+            Ok(res) => {
+                res.first_row()
+                    .and_then(|row| Result::ok(row.get_column(0)))
+                    .and_then(|column| Result::ok(column.get_i64()))
+                    .unwrap_or(0) > 0
+            }
+            Err(error) => {
+                error!("checking message {} of room {} failed: {}", msg_id, room_id, error);
+                false
+            }
+        }
+    }
+
+    async fn delete_message(&self, room_id: &str, msg_id: Uuid) -> error::Result<()> {
+        let statement = self.message_statement(Self::DELETE_QUERY, room_id, msg_id, 0).await
+            .ok_or_else(|| error::Error::System("Delete message failed".to_string()))?;
+
+        match self.statements.execute(&statement).await {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                Err(error::Error::System(format!("Delete message failed: {}", error)))
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use cassandra_cpp::*;
+
+use crate::cass::message_repository::CassMessageRepository;
+use crate::cass::repository::Utils;
+use crate::error;
+use crate::migration::{self, AppliedMigration, Migration, MigrationState, MigrationStatus};
+
+/// Applies the versioned migrations of `crate::migration` and records them in `schema_version`.
+pub struct Migrator {
+    session: Arc<Session>,
+    keyspace: String,
+}
+
+impl Migrator {
+    const SELECT_TABLE_QUERY: &'static str = "SELECT table_name FROM system_schema.tables \
+    WHERE keyspace_name = ? AND table_name = 'schema_version'";
+
+    const SELECT_COLUMNS_QUERY: &'static str = "SELECT table_name, column_name FROM system_schema.columns \
+    WHERE keyspace_name = ?";
+
+    /// Migration creating `room_message`; the messages of the retired `message` table are
+    /// copied over before it is recorded.
+    const ROOM_MESSAGE_VERSION: i32 = 7;
+
+    const BACKFILL_PAGE_SIZE: i32 = 500;
+
+    pub fn new(session: Arc<Session>, keyspace: &str) -> Self {
+        Migrator {
+            session,
+            keyspace: keyspace.to_string(),
+        }
+    }
+
+    /// State of every known migration; reads nothing but `schema_version`.
+    pub async fn status(&self) -> error::Result<Vec<MigrationStatus>> {
+        let applied = self.applied().await?;
+        Ok(migration::plan(&applied))
+    }
+
+    /// Migrations `apply` would run, without touching the cluster.
+    pub async fn dry_run(&self) -> error::Result<Vec<&'static Migration>> {
+        let status = self.status().await?;
+        Self::check_modified(&status)?;
+
+        Ok(status.into_iter()
+            .filter(|status| status.state == MigrationState::Pending)
+            .map(|status| status.migration)
+            .collect())
+    }
+
+    /// Creates the keyspace and `schema_version` if needed, then runs every pending migration
+    /// in order. Running it again once everything is applied does nothing.
+    pub async fn apply(&self) -> error::Result<Vec<&'static Migration>> {
+        self.execute_source(migration::KEYSPACE_SOURCE).await?;
+        self.execute_source(migration::SCHEMA_VERSION_SOURCE).await?;
+
+        let pending = self.dry_run().await?;
+        for migration in pending.iter() {
+            // a run that died before recording it may have added some of its columns already
+            let columns = self.columns().await?;
+            for statement in migration.remaining_statements(&self.keyspace, &columns) {
+                self.execute(statement.as_str()).await.map_err(|err| {
+                    error::Error::System(format!("migration {:04} {} failed: {}", migration.version, migration.name, err))
+                })?;
+            }
+            if migration.version == Self::ROOM_MESSAGE_VERSION {
+                self.backfill_messages().await.map_err(|err| {
+                    error::Error::System(format!("copying messages to room_message failed: {}", err))
+                })?;
+            }
+            self.record(migration).await?;
+        }
+
+        Ok(pending)
+    }
+
+    /// Copies every row of the retired `message` table into `room_message`, the table messages
+    /// are read from since migration 0007, and returns how many were copied. Rows keep their
+    /// ids, so running it again rewrites the same rows.
+    pub async fn backfill_messages(&self) -> error::Result<usize> {
+        let select = format!("SELECT id, from_id, from_name, room_id, body, created_at, toTimestamp(id), edited_at \
+        FROM {}.message", self.keyspace);
+        let insert_bucket = format!("INSERT INTO {}.room_message_bucket (room_id, bucket) VALUES (?, ?)", self.keyspace);
+        let insert = format!("INSERT INTO {}.room_message \
+        (room_id, bucket, id, from_id, from_name, body, created_at, edited_at) \
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)", self.keyspace);
+
+        let mut select = stmt!(select.as_str());
+        select.set_paging_size(Self::BACKFILL_PAGE_SIZE)?;
+        let mut copied = 0;
+        loop {
+            let result = self.session.execute(&select).await?;
+            for row in result.iter() {
+                let room_id: String = match row.get(3) {
+                    Ok(room_id) => room_id,
+                    Err(_) => continue,
+                };
+                let id: Uuid = row.get(0)?;
+                let from_id: Uuid = row.get(1)?;
+                let from_name: String = row.get(2)?;
+                let body: String = row.get(4).unwrap_or_default();
+                // rows written before 0005 have no created_at; their id holds the time
+                let created_at: i64 = match row.get(5) {
+                    Ok(created_at) => created_at,
+                    Err(_) => row.get(6)?,
+                };
+                let edited_at: Option<i64> = row.get(7).ok();
+                let bucket = CassMessageRepository::bucket_of(Utils::from_cass_uuid_to_uuid(id));
+
+                let mut statement = stmt!(insert_bucket.as_str());
+                statement.bind_string(0, room_id.as_str())?;
+                statement.bind_int32(1, bucket)?;
+                self.session.execute(&statement).await?;
+
+                let mut statement = stmt!(insert.as_str());
+                statement.bind_string(0, room_id.as_str())?;
+                statement.bind_int32(1, bucket)?;
+                statement.bind_uuid(2, id)?;
+                statement.bind_uuid(3, from_id)?;
+                statement.bind_string(4, from_name.as_str())?;
+                statement.bind_string(5, body.as_str())?;
+                statement.bind_int64(6, created_at)?;
+                match edited_at {
+                    Some(edited_at) => statement.bind_int64(7, edited_at)?,
+                    None => statement.bind_null(7)?,
+                };
+                self.session.execute(&statement).await?;
+                copied += 1;
+            }
+
+            if !result.has_more_pages() {
+                return Ok(copied);
+            }
+            select.set_paging_state(result)?;
+        }
+    }
+
+    /// Makes the keyspace the default for the statements the repositories prepare.
+    pub async fn use_keyspace(&self) -> error::Result<()> {
+        self.execute(format!("USE {}", self.keyspace).as_str()).await?;
+        Ok(())
+    }
+
+    async fn applied(&self) -> error::Result<Vec<AppliedMigration>> {
+        let mut statement = stmt!(Self::SELECT_TABLE_QUERY);
+        statement.bind_string(0, self.keyspace.as_str())?;
+        if self.session.execute(&statement).await?.first_row().is_none() {
+            return Ok(vec!());
+        }
+
+        let query = format!("SELECT version, name, checksum, applied_at FROM {}.schema_version", self.keyspace);
+        let result = self.execute(query.as_str()).await?;
+
+        let mut applied = vec!();
+        for row in result.iter() {
+            applied.push(AppliedMigration {
+                version: row.get(0)?,
+                name: row.get(1)?,
+                checksum: row.get(2)?,
+                applied_at: Utils::from_timestamp_to_datetime(row.get(3)?),
+            });
+        }
+        applied.sort_by_key(|migration| migration.version);
+        Ok(applied)
+    }
+
+    async fn columns(&self) -> error::Result<HashSet<(String, String)>> {
+        let mut statement = stmt!(Self::SELECT_COLUMNS_QUERY);
+        statement.bind_string(0, self.keyspace.as_str())?;
+        let result = self.session.execute(&statement).await?;
+
+        let mut columns = HashSet::new();
+        for row in result.iter() {
+            columns.insert((row.get(0)?, row.get(1)?));
+        }
+        Ok(columns)
+    }
+
+    async fn record(&self, migration: &Migration) -> error::Result<()> {
+        let query = format!("INSERT INTO {}.schema_version (version, name, checksum, applied_at) \
+        VALUES (?, ?, ?, ?)", self.keyspace);
+
+        let mut statement = stmt!(query.as_str());
+        statement.bind_int32(0, migration.version)?;
+        statement.bind_string(1, migration.name)?;
+        statement.bind_string(2, migration.checksum().as_str())?;
+        statement.bind_int64(3, chrono::Utc::now().timestamp())?;
+
+        self.session.execute(&statement).await?;
+        Ok(())
+    }
+
+    fn check_modified(status: &[MigrationStatus]) -> error::Result<()> {
+        match status.iter().find(|status| status.state == MigrationState::Modified) {
+            None => Ok(()),
+            Some(status) => Err(error::Error::System(format!(
+                "migration {:04} {} was changed after it was applied",
+                status.migration.version, status.migration.name
+            ))),
+        }
+    }
+
+    async fn execute_source(&self, source: &str) -> error::Result<()> {
+        for statement in migration::split_statements(source, &self.keyspace) {
+            self.execute(statement.as_str()).await?;
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, query: &str) -> error::Result<CassResult> {
+        let statement = stmt!(query);
+        Ok(self.session.execute(&statement).await?)
+    }
+}
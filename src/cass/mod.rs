@@ -1,2 +1,9 @@
-pub mod schema_loader;
-pub mod server_node;
\ No newline at end of file
+pub mod migrator;
+pub mod server_node;
+pub mod repository;
+pub mod statement_cache;
+pub mod room_repository;
+pub mod room_user_repository;
+pub mod user_repository;
+pub mod message_repository;
+pub mod account_repository;
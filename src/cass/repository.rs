@@ -0,0 +1,44 @@
+use std::str::FromStr;
+use cassandra_cpp::*;
+use chrono::{NaiveDateTime, DateTime, Utc};
+use uuid::Uuid;
+
+use crate::model::user::User;
+
+pub struct Utils {}
+
+impl Utils {
+
+    pub const SEPARATOR_CHARS: &'static str = "*&&*";
+
+    pub fn from_uuid_to_cass_uuid(uuid: Uuid) -> cassandra_cpp::Uuid {
+        cassandra_cpp::Uuid::from_str( uuid.to_string().as_str() ).ok().unwrap()
+    }
+
+    pub fn from_cass_uuid_to_uuid(cass_uuid: cassandra_cpp::Uuid) -> Uuid {
+        Uuid::from_str(cass_uuid.to_string().as_str()).ok().unwrap()
+    }
+
+    pub fn from_timestamp_to_datetime(timestamp: i64) -> DateTime<Utc> {
+        let datetime = NaiveDateTime::from_timestamp(timestamp, 0);
+        DateTime::from_utc(datetime, Utc)
+    }
+
+    pub fn from_millis_to_datetime(millis: i64) -> DateTime<Utc> {
+        let datetime = NaiveDateTime::from_timestamp(millis.div_euclid(1000), (millis.rem_euclid(1000) * 1_000_000) as u32);
+        DateTime::from_utc(datetime, Utc)
+    }
+
+    pub fn get_participants(participants: SetIterator) -> Option<Vec<User>> {
+        Some(
+            participants.map(|participant| {
+                let part_record = Result::ok(participant.get_string()).unwrap();
+                let items: Vec<&str> = part_record.split(Self::SEPARATOR_CHARS).collect();
+                User {
+                    id: Uuid::from_str(items.get(0).unwrap()).unwrap(),
+                    name: items.get(1).unwrap().to_string(),
+                }
+            }).collect()
+        )
+    }
+}
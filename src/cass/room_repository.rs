@@ -0,0 +1,212 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use cassandra_cpp::*;
+use chrono::{NaiveDateTime, DateTime, Utc};
+use log::{error, warn};
+use uuid::Uuid;
+
+use crate::model::room::Room;
+use crate::model::user::User;
+use crate::cass::repository::Utils;
+use crate::cass::statement_cache::StatementCache;
+use crate::domain::room_repository::RoomRepository;
+use crate::error;
+
+pub struct CassRoomRepository {
+    pub(crate) statements: Arc<StatementCache>
+}
+
+impl CassRoomRepository {
+    const INSERT_QUERY: &'static str = "INSERT INTO room (room_id, room_title, host_id, host_name, participants, create_at, delete_key) \
+    VALUES(?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS";
+
+    const UPDATE_PARTICIPANTS_QUERY: &'static str = "UPDATE room SET participants = ? WHERE room_id = ?";
+
+    const SELECT_ALL_QUERY: &'static str = "SELECT room_id, room_title, host_id, host_name, participants, create_at, delete_key FROM room";
+
+    const SELECT_ONE_QUERY: &'static str = "SELECT room_id, room_title, host_id, host_name, participants, create_at, delete_key FROM room \
+    WHERE room_id = ?";
+
+    const SELECT_EXISTS_QUERY: &'static str = "SELECT COUNT(*) FROM room WHERE room_id = ?";
+
+    const DELETE_QUERY: &'static str = "DELETE FROM room WHERE room_id = ?";
+
+    async fn statement(&self, query: &'static str) -> Option<Statement> {
+        match self.statements.statement(query).await {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                error!("preparing {:?} failed: {}", query, error);
+                None
+            }
+        }
+    }
+
+    /// The room of a row, or `None` when a column it needs is null or unreadable.
+    fn bind_to_room(row: Row) -> Option<Room> {
+        let host_id: cassandra_cpp::Uuid = Result::ok(row.get(2))?;
+        let participants: SetIterator = Result::ok(row.get(4))?;
+        let create_at: i64 = Result::ok(row.get(5))?;
+        let create_at = NaiveDateTime::from_timestamp(create_at, 0);
+        let create_at = DateTime::from_utc(create_at, Utc);
+
+        Some(Room {
+            room_id: Result::ok(row.get(0))?,
+            room_title: Result::ok( row.get(1))?,
+            host_info: User {
+                id: Uuid::from_str(host_id.to_string().as_str()).ok()?,
+                name: Result::ok(row.get(3))?,
+            },
+            participants: Utils::get_participants(participants),
+            create_at,
+            delete_key: Result::ok(row.get(6))?,
+        })
+    }
+}
+
+#[async_trait]
+impl RoomRepository for CassRoomRepository {
+
+    async fn create_room(&self, room: Room) -> Option<Room> {
+        let persistence_room = room.clone();
+        let mut statement = self.statement(Self::INSERT_QUERY).await?;
+        statement.bind_string(0, persistence_room.room_id.as_str()).ok();
+        let host_id = cassandra_cpp::Uuid::from_str(
+            persistence_room.host_info.id.to_string().as_str()
+        ).ok().unwrap();
+        statement.bind_string(1, persistence_room.room_title.as_str()).ok();
+        statement.bind_uuid(2, host_id).ok();
+        statement.bind_string(3, persistence_room.host_info.name.as_str()).ok();
+
+        let participants_set = match persistence_room.participants {
+            Some(participants) => {
+                let mut set = Set::new(participants.len());
+                for item in participants {
+                    let value = item.id.to_string() + Utils::SEPARATOR_CHARS + item.name.as_str();
+                    if let Err(error) = set.append_string(value.as_str()) {
+                        error!("adding participant {} to room {} failed: {:?}", item.id, persistence_room.room_id, error);
+                    }
+                }
+                set
+            }
+            None => Set::new_from_data_type(DataType::new(ValueType::VARCHAR), 0),
+        };
+        statement.bind_set(4, participants_set).ok();
+        statement.bind_int64(5, persistence_room.create_at.timestamp()).ok();
+        statement.bind_string(6, persistence_room.delete_key.as_str()).ok();
+
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Ok(result) => {
+                let applied: bool = result.first_row()
+                    .and_then(|row| Result::ok(row.get(0)))
+                    .unwrap_or(false);
+                if applied { Some(room) } else { None }
+            },
+            Err(error) => {
+                error!("failed to create room {}: {:?}", room.room_id, error);
+                None
+            }
+        }
+    }
+
+    async fn update_participant_in_room(&self, room_id: &str, participants: Vec<User>) -> error::Result<()> {
+        if self.load_one_room(room_id).await.is_none() {
+            warn!("room {} not found, participants not updated", room_id);
+            return Ok(());
+        }
+
+        let mut statement = self.statements.statement(Self::UPDATE_PARTICIPANTS_QUERY).await?;
+        let mut set = Set::new_from_data_type(DataType::new(ValueType::VARCHAR), participants.len());
+        participants.iter().for_each(|item| {
+            set.append_string(&(item.id.to_string() + Utils::SEPARATOR_CHARS + item.name.as_str())).ok();
+        });
+        statement.bind_set(0, set).ok();
+        statement.bind_string(1, room_id).ok();
+
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                error!("updating participants of room {} failed: {:?}", room_id, error);
+                Err(error::Error::System(error.to_string()))
+            }
+        }
+    }
+
+    async fn load_rooms(&self) -> Vec<Room> {
+        let statement = match self.statement(Self::SELECT_ALL_QUERY).await {
+            Some(statement) => statement,
+            None => return vec!(),
+        };
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Err(error) => {
+                error!("loading rooms failed: {:?}", error);
+                vec!()
+            },
+            Ok(cass_result) => cass_result.iter()
+                .filter_map(|row| {
+                    let room = Self::bind_to_room(row);
+                    if room.is_none() {
+                        warn!("skipping an unreadable room");
+                    }
+                    room
+                })
+                .collect(),
+        }
+    }
+
+    async fn load_one_room(&self, room_id: &str) -> Option<Room> {
+        let mut statement = self.statement(Self::SELECT_ONE_QUERY).await?;
+        statement.bind_string(0, room_id).ok();
+
+        match self.statements.execute(&statement).await {
+            Err(error) => {
+                error!("loading room {} failed: {:?}", room_id, error);
+                None
+            },
+            Ok(result) => {
+                let room = Self::bind_to_room(result.first_row()?);
+                if room.is_none() {
+                    warn!("room {} is unreadable", room_id);
+                }
+                room
+            }
+        }
+    }
+
+    async fn room_exists(&self, room_id: &str) -> bool {
+        let mut statement = match self.statement(Self::SELECT_EXISTS_QUERY).await {
+            Some(statement) => statement,
+            None => return false,
+        };
+        statement.bind_string(0, room_id).ok();
+
+        match self.statements.execute(&statement).await {
+            Err(error) => {
+                error!("checking room {} failed: {:?}", room_id, error);
+                false
+            },
+            Ok(result) => {
+                result.first_row()
+                    .and_then(|row| Result::ok(row.get_column(0)))
+                    .and_then(|column| Result::ok(column.get_i64()))
+                    .unwrap_or(0) > 0
+            }
+        }
+    }
+
+    async fn delete_room(&self, room_id: &str) -> error::Result<()> {
+        let mut statement = self.statements.statement(Self::DELETE_QUERY).await?;
+        statement.bind_string(0, room_id).ok();
+        let result = Result::ok(self.statements.execute(&statement).await);
+
+        match result {
+            Some(_) => Ok(()),
+            None => {
+                Err(error::Error::System("Delete room failed".to_string()))
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use cassandra_cpp::*;
+use chrono::Utc;
+use log::error;
+use uuid::Uuid;
+
+use crate::cass::repository::Utils;
+use crate::cass::statement_cache::StatementCache;
+use crate::domain::room_user_repository::RoomUserRepository;
+use crate::error;
+use crate::model::message::Message;
+use crate::model::room_user::RoomUser;
+
+pub struct CassRoomUserRepository {
+    pub(crate) statements: Arc<StatementCache>
+}
+
+impl CassRoomUserRepository {
+    const INSERT_QUERY: &'static str = "INSERT INTO \
+    room_users (room_id, user_id, username, room_title, create_at) \
+    VALUES(?, ?, ?, ?, ?)";
+
+    const SELECT_BY_USER: &'static str = "SELECT room_id, user_id, username, room_title, create_at, last_read FROM room_users WHERE user_id = ? ALLOW FILTERING";
+
+    const SELECT_ALL_BY_ROOM: &'static str = "SELECT room_id, user_id, username, room_title, create_at, last_read FROM room_users WHERE room_id = ?";
+
+    // the conditions keep markers moving forward, and keep a marker from recreating a member
+    // removed meanwhile
+    const SET_FIRST_LAST_READ: &'static str = "UPDATE room_users SET last_read = ? WHERE room_id = ? AND user_id = ? \
+    IF username != null AND last_read = null";
+
+    const ADVANCE_LAST_READ: &'static str = "UPDATE room_users SET last_read = ? WHERE room_id = ? AND user_id = ? \
+    IF last_read < ?";
+
+    /// Attempts at moving a read marker that other writers keep moving meanwhile.
+    const LAST_READ_ATTEMPTS: usize = 3;
+
+    const SELECT_ONE: &'static str = "SELECT room_id, user_id, username, room_title, create_at, last_read FROM room_users WHERE room_id = ? AND user_id = ?";
+
+    const DELETE_QUERY: &'static str = "DELETE FROM room_users WHERE room_id = ? AND user_id = ?";
+
+    const DELETE_BY_ROOM: &'static str = "DELETE FROM room_users WHERE room_id = ?";
+
+    fn bind_to_roomuser(row: Row) -> Option<RoomUser> {
+        let user_id: cassandra_cpp::Uuid = Result::ok( row.get(1) ).unwrap();
+        Some(
+            RoomUser {
+                room_id: Result::ok(row.get(0)).unwrap(),
+                user_id: Utils::from_cass_uuid_to_uuid(user_id),
+                username: Result::ok( row.get(2)).unwrap(),
+                room_title: Result::ok(row.get(3)).unwrap(),
+                create_at: Utils::from_timestamp_to_datetime(
+                    Result::ok(row.get(4)).unwrap()
+                ),
+                last_read: Result::ok(row.get(5)).map(Utils::from_cass_uuid_to_uuid),
+            }
+        )
+    }
+}
+
+#[async_trait]
+impl RoomUserRepository for CassRoomUserRepository {
+
+    async fn create_room_users(&self, input: RoomUser) -> Option<RoomUser> {
+        let mut statement = self.statements.statement(Self::INSERT_QUERY).await.ok()?;
+
+        statement.bind_string(0, input.room_id.as_str()).ok();
+        statement.bind_uuid(1, Utils::from_uuid_to_cass_uuid(input.user_id)).ok();
+        statement.bind_string(2, input.username.as_str()).ok();
+        statement.bind_string(3, input.room_title.as_str()).ok();
+        statement.bind_int64(4, Utc::now().timestamp()).ok();
+
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Ok(_) => Some(input),
+            Err(error) => {
+                error!("adding {} to room {} failed: {:?}", input.user_id, input.room_id, error);
+                None
+            }
+        }
+    }
+
+    async fn load_by_userid(&self, user_id: Uuid, page: i32, size: i32) -> Option<Vec<RoomUser>> {
+        let mut res = Vec::<RoomUser>::new();
+
+        let mut has_more_pages = true;
+        let mut paging = page - 1;
+
+        let mut statement = self.statements.statement(Self::SELECT_BY_USER).await.ok()?;
+        statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid(user_id)).ok();
+        statement.set_paging_size(size).ok();
+
+        while has_more_pages && paging >= 0 {
+            match self.statements.execute(&statement).await.ok() {
+                None => break,
+                Some(result) => {
+
+                    if paging == 0 {
+                        for row in result.iter() {
+                            match Self::bind_to_roomuser(row) {
+                                None => continue,
+                                Some(user) => res.push(user),
+                            };
+                        }
+                    }
+
+                    has_more_pages = result.has_more_pages();
+                    if has_more_pages {
+                        statement.set_paging_state(result).ok();
+                    }
+                    paging -= 1;
+                }
+            };
+        }
+
+        Some(res)
+    }
+
+    async fn load_by_room(&self, room_id: String) -> Option<Vec<RoomUser>> {
+        let mut res = Vec::<RoomUser>::new();
+        let mut statement = self.statements.statement(Self::SELECT_ALL_BY_ROOM).await.ok()?;
+        statement.bind_string(0, room_id.as_str()).ok();
+
+        match self.statements.execute(&statement).await.ok() {
+            None => None,
+            Some(result) => {
+                for row in result.iter() {
+                    match Self::bind_to_roomuser(row) {
+                        None => continue,
+                        Some(room_user) => res.push(room_user),
+                    }
+                }
+                Some(res)
+            }
+        }
+    }
+
+    async fn load_one(&self, room_id: String, user_id: Uuid) -> Option<RoomUser> {
+        let mut statement = self.statements.statement(Self::SELECT_ONE).await.ok()?;
+        statement.bind_string(0, room_id.as_str()).ok();
+        statement.bind_uuid(1, Utils::from_uuid_to_cass_uuid(user_id)).ok();
+        let result = self.statements.execute(&statement).await.ok()?;
+        result.first_row().and_then(Self::bind_to_roomuser)
+    }
+
+    async fn advance_last_read(&self, room_id: String, user_id: Uuid, msg_id: Uuid) -> Option<bool> {
+        for _ in 0..Self::LAST_READ_ATTEMPTS {
+            let member = self.load_one(room_id.clone(), user_id).await?;
+            let mut statement = match member.last_read {
+                Some(last_read) if Message::id_order(&last_read) >= Message::id_order(&msg_id) => return Some(false),
+                Some(_) => {
+                    let mut statement = self.statements.statement(Self::ADVANCE_LAST_READ).await.ok()?;
+                    statement.bind_uuid(3, Utils::from_uuid_to_cass_uuid(msg_id)).ok();
+                    statement
+                },
+                None => self.statements.statement(Self::SET_FIRST_LAST_READ).await.ok()?,
+            };
+            statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid(msg_id)).ok();
+            statement.bind_string(1, room_id.as_str()).ok();
+            statement.bind_uuid(2, Utils::from_uuid_to_cass_uuid(user_id)).ok();
+
+            let applied: bool = match self.statements.execute(&statement).await {
+                Ok(result) => result.first_row().and_then(|row| Result::ok(row.get(0)))?,
+                Err(error) => {
+                    error!("moving read marker of {} in room {} failed: {}", user_id, room_id, error);
+                    return None;
+                }
+            };
+            if applied {
+                return Some(true);
+            }
+            // someone else moved or removed the marker meanwhile; look again
+        }
+        Some(false)
+    }
+
+    async fn delete_room_user(&self, room_id: String, user_id: Uuid) -> error::Result<()> {
+        let mut statement = self.statements.statement(Self::DELETE_QUERY).await?;
+        statement.bind_string(0, room_id.as_str()).ok();
+        let cass_uuid = cassandra_cpp::Uuid::from_str( user_id.to_string().as_str() ).ok().unwrap();
+        statement.bind_uuid(1, cass_uuid).ok();
+
+        let result = Result::ok(self.statements.execute(&statement).await);
+
+        match result {
+            Some(_) => Ok(()),
+            None => {
+                Err(error::Error::System("Delete user failed".to_string()))
+            }
+        }
+    }
+
+    async fn delete_by_room(&self, room_id: String) -> error::Result<()> {
+        let mut statement = self.statements.statement(Self::DELETE_BY_ROOM).await?;
+        statement.bind_string(0, room_id.as_str()).ok();
+        let result = Result::ok(self.statements.execute(&statement).await);
+
+        match result {
+            Some(_) => Ok(()),
+            None => {
+                Err(error::Error::System("Delete user failed".to_string()))
+            }
+        }
+    }
+}
@@ -1,70 +1,70 @@
+use std::sync::Arc;
 use cassandra_cpp::*;
+use log::info;
 
-use crate::cass::schema_loader::SchemaLoader;
+use crate::cass::migrator::Migrator;
+use crate::config::CassandraConfig;
+use crate::error;
 
 #[derive(Default)]
 pub struct ServerNode {
     cluster_instance: Cluster,
+    config: CassandraConfig,
+    session: Option<Arc<Session>>,
 }
 
 impl ServerNode {
 
     pub fn new() -> Self {
+        Self::with_config(CassandraConfig::default())
+    }
+
+    pub fn with_config(config: CassandraConfig) -> Self {
         ServerNode {
             cluster_instance: Cluster::default(),
+            config,
+            session: None,
         }
     }
 
-    pub async fn init(&mut self) -> Result<()> {
-        match self.init_session().await {
-            Ok(ref mut session) => {
-                let schema_loader = SchemaLoader::new_with_session(session);
-                Self::load_schema_from_file(&schema_loader, "cql/keyspace.cql").await;
-                Self::load_schema_from_file(&schema_loader, "cql/room.cql").await;
-                Self::load_schema_from_file(&schema_loader, "cql/user.cql").await;
-                Self::load_schema_from_file(&schema_loader, "cql/room_users.cql").await;
-                Self::load_schema_from_file(&schema_loader, "cql/message.cql").await;
+    /// Connects, brings the schema up to date and keeps the session for the repositories.
+    pub async fn init(&mut self) -> error::Result<()> {
+        let session = self.init_session().await?;
 
-                Ok(())
-            },
-            _ => panic!()
+        let migrator = Migrator::new(Arc::clone(&session), &self.config.keyspace);
+        for migration in migrator.apply().await? {
+            info!("applied migration {:04} {}", migration.version, migration.name);
         }
+        migrator.use_keyspace().await?;
+
+        self.session = Some(session);
+        Ok(())
     }
-    
-    async fn init_session(&mut self) -> Result<Session> {
-        self.cluster_instance.set_contact_points("127.0.0.1").unwrap();
-        self.cluster_instance.set_load_balance_round_robin();
-        
-        if let Err(error) = self.cluster_instance.set_protocol_version(4) {
-            println!("{:?}", error);
-        }
-        
-        self.cluster_instance.connect_async().await
+
+    /// Connects without changing the schema, for the migration commands.
+    pub async fn migrator(&mut self) -> error::Result<Migrator> {
+        let session = self.init_session().await?;
+        Ok(Migrator::new(session, &self.config.keyspace))
     }
 
-    pub fn build_cass_cluster() -> Result<Cluster> {
-        let mut cluster_instance = Cluster::default();
-        cluster_instance.set_contact_points("127.0.0.1").unwrap();
-        cluster_instance.set_load_balance_round_robin();
+    /// The long-lived session opened by `init`, shared by every repository.
+    pub fn session(&self) -> Option<Arc<Session>> {
+        self.session.as_ref().map(Arc::clone)
+    }
+    
+    async fn init_session(&mut self) -> Result<Arc<Session>> {
+        self.cluster_instance.set_contact_points(self.config.contact_points.join(",").as_str())?;
+        self.cluster_instance.set_load_balance_round_robin();
 
-        if let Err(error) = cluster_instance.set_protocol_version(4) {
-            println!("{:?}", error);
-            Err(error)
-        }
-        else {
-            Ok(cluster_instance)
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            self.cluster_instance.set_credentials(username.as_str(), password.as_str())?;
         }
-    }
 
-    async fn load_schema_from_file(schema_loader: &SchemaLoader, file_path: &str) {
-        match schema_loader.load_from_file(file_path.to_string()).await {
-            Ok(result) => {
-                println!("{}", result.to_string());
-            },
-            Err(error) => {
-                panic!("Error occur: {:?}", error);
-            }
+        if let Err(error) = self.cluster_instance.set_protocol_version(self.config.protocol_version) {
+            println!("{:?}", error);
         }
+        
+        self.cluster_instance.connect_async().await.map(Arc::new)
     }
 }
 
@@ -90,4 +90,4 @@ mod tests {
             }
         }
     }
-}
\ No newline at end of file
+}
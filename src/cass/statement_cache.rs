@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use cassandra_cpp::*;
+use tokio::sync::RwLock;
+
+/// Prepared statements of one session, keyed by their query text and shared by every repository.
+///
+/// The cache is built once `ServerNode::init` has applied the migrations, so it never sees the
+/// schema change under it; the driver itself prepares a statement again on nodes answering
+/// UNPREPARED. Migrations applied by `migrate` while serving take effect on the next start.
+pub struct StatementCache {
+    session: Arc<Session>,
+    prepared: RwLock<HashMap<&'static str, Arc<PreparedStatement>>>,
+}
+
+impl StatementCache {
+
+    pub fn new(session: Arc<Session>) -> Self {
+        StatementCache {
+            session,
+            prepared: Default::default(),
+        }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Binds a new statement from the cached preparation, preparing the query on first use.
+    pub async fn statement(&self, query: &'static str) -> Result<Statement> {
+        if let Some(prepared) = self.prepared.read().await.get(query) {
+            return Ok(prepared.bind());
+        }
+
+        let prepared = self.prepare(query).await?;
+        Ok(prepared.bind())
+    }
+
+    pub fn execute(&self, statement: &Statement) -> CassFuture<CassResult> {
+        self.session.execute(statement)
+    }
+
+    async fn prepare(&self, query: &'static str) -> Result<Arc<PreparedStatement>> {
+        let future = self.session.prepare(query)?;
+        let prepared = Arc::new(future.await?);
+        self.prepared.write().await.insert(query, Arc::clone(&prepared));
+        Ok(prepared)
+    }
+}
@@ -0,0 +1,198 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use cassandra_cpp::*;
+use chrono::Utc;
+use log::error;
+use uuid::Uuid;
+
+use crate::model::user::User;
+use crate::cass::repository::Utils;
+use crate::cass::statement_cache::StatementCache;
+use crate::domain::user_repository::UserRepository;
+use crate::error;
+
+pub struct CassUserRepository {
+    pub(crate) statements: Arc<StatementCache>
+}
+
+impl CassUserRepository {
+    const INSERT_QUERY: &'static str = "INSERT INTO user (id, name, create_at) VALUES(?, ?, ?) IF NOT EXISTS";
+
+    const SELECT_ALL_QUERY: &'static str = "SELECT id, name FROM user ORDER BY create_at DESC";
+
+    const SELECT_ONE_QUERY: &'static str = "SELECT id, name FROM user WHERE id = ?";
+
+    const SELECT_HOST_USER: &'static str = "SELECT host_id, host_name FROM room WHERE room_id = ?";
+
+    const SELECT_PARTICIPANTS: &'static str = "SELECT participants FROM room WHERE room_id = ?";
+
+    const SELECT_EXISTS_QUERY: &'static str = "SELECT COUNT(*) FROM user WHERE name = ? ALLOW FILTERING";
+
+    const DELETE_QUERY: &'static str = "DELETE FROM user WHERE id = ?";
+
+    fn bind_to_user(row: Row) -> Option<User> {
+        let user_id: cassandra_cpp::Uuid = Result::ok( row.get(0) ).unwrap();
+        Some(
+            User {
+                id: Utils::from_cass_uuid_to_uuid(user_id),
+                name: Result::ok( row.get(1) ).unwrap(),
+            }
+        )
+    }
+}
+
+#[async_trait]
+impl UserRepository for CassUserRepository {
+
+    async fn create_user(&self, user: User) -> Option<User> {
+        let persistent_user = user.clone();
+        let mut statement = self.statements.statement(Self::INSERT_QUERY).await.ok()?;
+
+        statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid(persistent_user.id)).ok();
+        statement.bind_string(1, persistent_user.name.as_str()).ok();
+        statement.bind_int64(2, Utc::now().timestamp()).ok();
+
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Ok(result) => {
+                let applied: bool = result.first_row()
+                    .and_then(|row| Result::ok(row.get(0)))
+                    .unwrap_or(false);
+                if applied { Some(user) } else { None }
+            },
+            Err(error) => {
+                error!("failed to create user {}: {:?}", user.id, error);
+                None
+            }
+        }
+    }
+
+    async fn load_users(&self, page: i32, size: i32) -> Option<Vec<User>> {
+        let mut res = Vec::<User>::new();
+
+        let mut has_more_pages = true;
+        let mut paging = page - 1;
+
+        let mut statement = self.statements.statement(Self::SELECT_ALL_QUERY).await.ok()?;
+        statement.set_paging_size(size).ok();
+
+        while has_more_pages && paging >= 0 {
+            match self.statements.execute(&statement).await.ok() {
+                None => break,
+                Some(result) => {
+
+                    if paging == 0 {
+                        for row in result.iter() {
+                            match Self::bind_to_user(row) {
+                                None => continue,
+                                Some(user) => res.push(user),
+                            };
+                        }
+                    }
+
+                    has_more_pages = result.has_more_pages();
+                    if has_more_pages {
+                        statement.set_paging_state(result).ok();
+                    }
+                    paging -= 1;
+                }
+            };
+        }
+
+        Some(res)
+    }
+
+    async fn load_one_user(&self, id: Uuid) -> Option<User> {
+        let mut statement = self.statements.statement(Self::SELECT_ONE_QUERY).await.ok()?;
+        statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid( id )).ok();
+
+        match self.statements.execute(&statement).await {
+            Err(error) => {
+                error!("loading user {} failed: {:?}", id, error);
+                None
+            },
+            Ok(result) => {
+                match result.first_row() {
+                    None => None,
+                    Some(row) => Self::bind_to_user(row)
+                }
+            }
+        }
+    }
+
+    async fn load_host_room(&self, room_id: &str) -> Option<User> {
+        let mut statement = self.statements.statement(Self::SELECT_HOST_USER).await.ok()?;
+        statement.bind_string(0, room_id).ok();
+
+        let result = self.statements.execute(&statement).await;
+        match result {
+            Err(_) => None,
+            Ok(cass_result) => {
+                if let Some(row) = cass_result.first_row() {
+                    let host_id: cassandra_cpp::Uuid = Result::ok(row.get(0)).unwrap();
+                    Some(User {
+                        id: Utils::from_cass_uuid_to_uuid(host_id),
+                        name: Result::ok( row.get(1) ).unwrap(),
+                    })
+                }
+                else {
+                    None
+                }
+            }
+        }
+    }
+
+    async fn load_participants_room(&self, room_id: &str) -> Option<Vec<User>> {
+        let mut statement = self.statements.statement(Self::SELECT_PARTICIPANTS).await.ok()?;
+        statement.bind_string(0, room_id).ok();
+
+        match self.statements.execute(&statement).await {
+            Err(_) => None,
+            Ok(cass_result) => {
+                if let Some(row) = cass_result.first_row() {
+                    Utils::get_participants( Result::ok( row.get(0) ).unwrap() )
+                }
+                else {
+                    None
+                }
+            }
+        }
+    }
+
+    async fn user_exists(&self, user: User) -> bool {
+        let mut statement = match self.statements.statement(Self::SELECT_EXISTS_QUERY).await {
+            Ok(statement) => statement,
+            Err(_) => return false,
+        };
+        // statement.bind_uuid(0, Utils::from_uuid_to_cass_uuid(user.id)).ok();
+        statement.bind_string(0, user.name.as_str()).ok();
+
+        match self.statements.execute(&statement).await {
+            Err(error) => {
+                error!("checking user {} failed: {:?}", user.name, error);
+                false
+            },
+            Ok(result) => {
+                result.first_row()
+                    .and_then(|row| Result::ok(row.get_column(0)))
+                    .and_then(|column| Result::ok(column.get_i64()))
+                    .unwrap_or(0) > 0
+            }
+        }
+    }
+
+    async fn delete_user(&self, id: Uuid) -> error::Result<()> {
+        let mut statement = self.statements.statement(Self::DELETE_QUERY).await?;
+        let cass_uuid = cassandra_cpp::Uuid::from_str( id.to_string().as_str() ).ok().unwrap();
+        statement.bind_uuid(0, cass_uuid).ok();
+
+        let result = Result::ok(self.statements.execute(&statement).await);
+        match result {
+            Some(_) => Ok(()),
+            None => {
+                Err(error::Error::System("Delete user failed".to_string()))
+            }
+        }
+    }
+}
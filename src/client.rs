@@ -1,14 +1,8 @@
-use std::{error, result};
-
-use futures::stream::SplitStream;
-use futures::{future, Stream, StreamExt, TryStream, TryStreamExt};
+use futures::{future, Stream, StreamExt};
 use uuid::Uuid;
-use warp::filters::ws::WebSocket;
 
 use crate::error::{Error, Result};
 use crate::proto::{InputParcel, OutputParcel, Input};
-use crate::proto::Input::{PostMessage, JoinRoom};
-use std::str::FromStr;
 
 #[derive(Clone, Default)]
 pub struct RoomClient {
@@ -17,39 +11,35 @@ pub struct RoomClient {
 }
 
 impl RoomClient {
-  pub fn new(room_id: String) -> Self {
-    RoomClient { room_id, id: Uuid::default() }
+  pub fn new(room_id: String, user_id: Uuid) -> Self {
+    RoomClient { room_id, id: user_id }
   }
 
-  pub fn read_input(
-    &self,
-    stream: SplitStream<WebSocket>
-  ) -> impl Stream<Item = Result<InputParcel>> {
+  pub fn read_input<S>(&self, stream: S) -> impl Stream<Item = Result<InputParcel>>
+  where
+    S: Stream<Item = std::result::Result<warp::ws::Message, warp::Error>>,
+  {
     let rid = self.room_id.clone();
-    let mut client_id = self.id;
+    let client_id = self.id;
 
     stream
       // take only text messages
       .take_while(|message| {
         future::ready(if let Ok(message) = message {
-          message.is_ping() || message.is_text()
+          message.is_ping() || message.is_pong() || message.is_text()
         } else {
           false
         })
       })
+      // pongs only answer our heartbeat
+      .filter(|message| future::ready(!matches!(message, Ok(message) if message.is_pong())))
       // deserialize json
       .map(move |message| match message {
         Err(err) => Err(Error::System(err.to_string())),
         Ok(message) => {
           if message.is_text() {
             let input: Input = serde_json::from_str(message.to_str().unwrap())?;
-            if let PostMessage(post) = input.clone() {
-              client_id = post.client_id;
-            }
-
-            if let JoinRoom(join) = input.clone() {
-              client_id = join.client_id;
-            }
+            check_claimed_id(&input, client_id)?;
             Ok(InputParcel::new(client_id, rid.clone(), input))
           }
           else if message.is_ping() {
@@ -63,25 +53,18 @@ impl RoomClient {
       })
   }
 
-  pub fn write_output<S, E>(&self, stream: S) -> impl Stream<Item = Result<warp::ws::Message>>
-  where 
-    S: TryStream<Ok = OutputParcel, Error = E> + Stream<Item = result::Result<OutputParcel, E>>,
-    E: error::Error,
+  pub fn write_output<S>(&self, stream: S) -> impl Stream<Item = Result<warp::ws::Message>>
+  where
+    S: Stream<Item = OutputParcel>,
   {
-    let room_id = self.room_id.clone();
     stream
-      // skip irrelevant parcels
-      .try_filter(move |output_parcel| {
-        future::ready(output_parcel.room_id == room_id)
-      })
       // serialize to JSON
-      .map_ok(|output_parcel| {
-        let data = serde_json::to_string(&output_parcel.output).unwrap();
-        // println!("{}", data);
-        warp::ws::Message::text(data)
+      .map(|output_parcel| {
+        let data = serde_json::to_string(&output_parcel.output)?;
+        Ok(warp::ws::Message::text(data))
       })
-      .map_err(|err| Error::System(err.to_string()))
   }
+
 }
 
 #[derive(Clone, Default)]
@@ -90,31 +73,34 @@ pub struct UserClient {
 }
 
 impl UserClient {
-  pub fn new(user_id: String) -> Self {
-    UserClient { id: Uuid::from_str(user_id.as_str()).unwrap() }
+  pub fn new(user_id: Uuid) -> Self {
+    UserClient { id: user_id }
   }
 
-  pub fn read_input(
-    &self,
-    stream: SplitStream<WebSocket>
-  ) -> impl Stream<Item = Result<InputParcel>> {
+  pub fn read_input<S>(&self, stream: S) -> impl Stream<Item = Result<InputParcel>>
+    where
+        S: Stream<Item = std::result::Result<warp::ws::Message, warp::Error>>,
+  {
     let client_id = self.id;
 
     stream
         // take only text messages
         .take_while(|message| {
           future::ready(if let Ok(message) = message {
-            message.is_ping() || message.is_text()
+            message.is_ping() || message.is_pong() || message.is_text()
           } else {
             false
           })
         })
+        // pongs only answer our heartbeat
+        .filter(|message| future::ready(!matches!(message, Ok(message) if message.is_pong())))
         // deserialize json
         .map(move |message| match message {
           Err(err) => Err(Error::System(err.to_string())),
           Ok(message) => {
             if message.is_text() {
               let input: Input = serde_json::from_str(message.to_str().unwrap())?;
+              check_claimed_id(&input, client_id)?;
               Ok(InputParcel::new(client_id, "".to_string(), input))
             }
             else if message.is_ping() {
@@ -127,22 +113,53 @@ impl UserClient {
         })
   }
 
-  pub fn write_output<S, E>(&self, stream: S) -> impl Stream<Item = Result<warp::ws::Message>>
+  pub fn write_output<S>(&self, stream: S) -> impl Stream<Item = Result<warp::ws::Message>>
     where
-        S: TryStream<Ok = OutputParcel, Error = E> + Stream<Item = result::Result<OutputParcel, E>>,
-        E: error::Error,
+        S: Stream<Item = OutputParcel>,
   {
-    let user_id = self.id;
     stream
-        // skip irrelevent parcels
-        .try_filter(move |output_parcel| {
-          future::ready(output_parcel.client_id == user_id)
-        })
         // serialize to JSON
-        .map_ok(|output_parcel| {
-          let data = serde_json::to_string(&output_parcel.output).unwrap();
-          warp::ws::Message::text(data)
+        .map(|output_parcel| {
+          let data = serde_json::to_string(&output_parcel.output)?;
+          Ok(warp::ws::Message::text(data))
         })
-        .map_err(|err| Error::System(err.to_string()))
   }
-}
\ No newline at end of file
+
+}
+
+/// Connections speak for the user their token names; an input claiming another one ends them.
+fn check_claimed_id(input: &Input, client_id: Uuid) -> Result<()> {
+  match input.claimed_id() {
+    Some(claimed) if claimed != client_id => {
+      Err(Error::Forbidden(format!("input for {} on a connection of {}", claimed, client_id)))
+    },
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use warp::ws::Message;
+
+  macro_rules! aw {
+    ($e:expr) => {
+      tokio_test::block_on($e)
+    };
+  }
+
+  fn post(client_id: Uuid) -> std::result::Result<Message, warp::Error> {
+    Ok(Message::text(format!(r#"{{"type":"post-message","payload":{{"clientId":"{}","body":"hi"}}}}"#, client_id)))
+  }
+
+  #[test]
+  fn test001_inputs_for_another_user_are_refused() {
+    let alice = Uuid::new_v4();
+    let client = RoomClient::new("room".to_string(), alice);
+
+    let inputs: Vec<Result<InputParcel>> = aw!(client.read_input(futures::stream::iter(vec![post(alice), post(Uuid::new_v4())])).collect());
+    assert_eq!(inputs.len(), 2);
+    assert!(matches!(&inputs[0], Ok(parcel) if parcel.client_id == alice));
+    assert!(matches!(inputs[1], Err(Error::Forbidden(_))));
+  }
+}
@@ -0,0 +1,411 @@
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use regex::Regex;
+use serde::Deserialize;
+use structopt::StructOpt;
+
+use crate::domain::repository::Storage;
+use crate::error::{Error, Result};
+
+const DEFAULT_CONFIG_FILE: &str = "chat-server.toml";
+const CONFIG_FILE_VAR: &str = "CHAT_CONFIG";
+const ENV_PREFIX: &str = "CHAT_";
+const MIN_AUTH_SECRET_LENGTH: usize = 32;
+
+lazy_static! {
+  static ref KEYSPACE_REGEX: Regex = Regex::new("^[A-Za-z][A-Za-z0-9_]{0,47}$").unwrap();
+}
+
+/// Settings shared by both servers, loaded from defaults, a TOML file,
+/// `CHAT_*` environment variables and command line flags, in that order.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub storage: Storage,
+  pub server: ServerConfig,
+  pub cassandra: CassandraConfig,
+  pub channels: ChannelConfig,
+  pub pages: PageConfig,
+  pub heartbeat: HeartbeatConfig,
+  pub auth: AuthConfig,
+  pub max_message_body_length: usize,
+  /// Recent messages each room keeps in memory; older ones are read from storage.
+  pub feed_capacity: usize,
+  /// Seconds a room may stay without connections before its hub is unloaded.
+  pub hub_idle_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+  pub bind: SocketAddr,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CassandraConfig {
+  pub contact_points: Vec<String>,
+  pub keyspace: String,
+  pub protocol_version: i32,
+  pub username: Option<String>,
+  pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelConfig {
+  pub room_output: usize,
+  pub user_output: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PageConfig {
+  pub messages: i32,
+  pub rooms: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+  /// Seconds between `alive` outputs and websocket pings.
+  pub interval_secs: u64,
+  /// Seconds of silence after which a connection is closed.
+  pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+  /// Key signing the bearer tokens; serving refuses to start without one.
+  pub secret: Option<String>,
+  /// Seconds a token handed out at login stays valid.
+  pub token_ttl_secs: u64,
+}
+
+// Command line flags overriding the configuration file and environment;
+// flattened into the binary's arguments.
+#[derive(Debug, Default, StructOpt)]
+pub struct ConfigArgs {
+  /// Path of the TOML configuration file
+  #[structopt(short, long, parse(from_os_str))]
+  pub config: Option<PathBuf>,
+
+  /// Storage backend: memory or cassandra
+  #[structopt(long)]
+  pub storage: Option<Storage>,
+
+  /// Address the server listens on
+  #[structopt(long)]
+  pub bind: Option<SocketAddr>,
+
+  /// Comma separated Cassandra contact points
+  #[structopt(long)]
+  pub contact_points: Option<String>,
+
+  /// Cassandra keyspace holding the chat tables
+  #[structopt(long)]
+  pub keyspace: Option<String>,
+
+  /// Longest accepted message body, in bytes
+  #[structopt(long)]
+  pub max_message_body_length: Option<usize>,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      storage: Storage::default(),
+      server: ServerConfig { bind: ([127, 0, 0, 1], 8889).into() },
+      cassandra: CassandraConfig::default(),
+      channels: ChannelConfig::default(),
+      pages: PageConfig::default(),
+      heartbeat: HeartbeatConfig::default(),
+      auth: AuthConfig::default(),
+      max_message_body_length: 256,
+      feed_capacity: 100,
+      hub_idle_secs: 300,
+    }
+  }
+}
+
+impl Default for CassandraConfig {
+  fn default() -> Self {
+    CassandraConfig {
+      contact_points: vec!["127.0.0.1".to_string()],
+      keyspace: "chat_app".to_string(),
+      protocol_version: 4,
+      username: None,
+      password: None,
+    }
+  }
+}
+
+impl Default for ChannelConfig {
+  fn default() -> Self {
+    ChannelConfig {
+      room_output: 256,
+      user_output: 16,
+    }
+  }
+}
+
+impl Default for PageConfig {
+  fn default() -> Self {
+    PageConfig {
+      messages: 15,
+      rooms: 10,
+    }
+  }
+}
+
+impl Default for HeartbeatConfig {
+  fn default() -> Self {
+    HeartbeatConfig {
+      interval_secs: 30,
+      timeout_secs: 90,
+    }
+  }
+}
+
+impl Default for AuthConfig {
+  fn default() -> Self {
+    AuthConfig {
+      secret: None,
+      token_ttl_secs: 24 * 60 * 60,
+    }
+  }
+}
+
+impl Config {
+
+  /// Reads the file given by `--config`, `CHAT_CONFIG` or `chat-server.toml` when present,
+  /// applies environment and command line overrides and validates the result.
+  pub fn load(args: &ConfigArgs) -> Result<Self> {
+    let path = args.config.clone()
+      .or_else(|| env::var_os(CONFIG_FILE_VAR).map(PathBuf::from));
+
+    let mut config = match path {
+      Some(path) => Self::from_file(&path)?,
+      None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+      None => Config::default(),
+    };
+
+    config.apply_env(env::vars())?;
+    config.apply_args(args);
+    config.validate()?;
+    Ok(config)
+  }
+
+  pub fn from_file(path: &Path) -> Result<Self> {
+    let content = fs::read_to_string(path)
+      .map_err(|err| Error::Config(format!("unable to read {}: {}", path.display(), err)))?;
+
+    Self::from_toml(&content)
+      .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))
+  }
+
+  pub fn from_toml(content: &str) -> Result<Self> {
+    toml::from_str(content).map_err(|err| Error::Config(err.to_string()))
+  }
+
+  pub fn apply_env<I>(&mut self, vars: I) -> Result<()>
+  where
+    I: IntoIterator<Item = (String, String)>,
+  {
+    for (key, value) in vars {
+      let name = match key.strip_prefix(ENV_PREFIX) {
+        Some(name) => name,
+        None => continue,
+      };
+
+      match name {
+        "STORAGE" => self.storage = parse_var(&key, &value)?,
+        "SERVER_BIND" => self.server.bind = parse_var(&key, &value)?,
+        "CASSANDRA_CONTACT_POINTS" => self.cassandra.contact_points = split_list(&value),
+        "CASSANDRA_KEYSPACE" => self.cassandra.keyspace = value,
+        "CASSANDRA_PROTOCOL_VERSION" => self.cassandra.protocol_version = parse_var(&key, &value)?,
+        "CASSANDRA_USERNAME" => self.cassandra.username = Some(value),
+        "CASSANDRA_PASSWORD" => self.cassandra.password = Some(value),
+        "CHANNELS_ROOM_OUTPUT" => self.channels.room_output = parse_var(&key, &value)?,
+        "CHANNELS_USER_OUTPUT" => self.channels.user_output = parse_var(&key, &value)?,
+        "PAGES_MESSAGES" => self.pages.messages = parse_var(&key, &value)?,
+        "PAGES_ROOMS" => self.pages.rooms = parse_var(&key, &value)?,
+        "HEARTBEAT_INTERVAL_SECS" => self.heartbeat.interval_secs = parse_var(&key, &value)?,
+        "HEARTBEAT_TIMEOUT_SECS" => self.heartbeat.timeout_secs = parse_var(&key, &value)?,
+        "AUTH_SECRET" => self.auth.secret = Some(value),
+        "AUTH_TOKEN_TTL_SECS" => self.auth.token_ttl_secs = parse_var(&key, &value)?,
+        "MAX_MESSAGE_BODY_LENGTH" => self.max_message_body_length = parse_var(&key, &value)?,
+        "FEED_CAPACITY" => self.feed_capacity = parse_var(&key, &value)?,
+        "HUB_IDLE_SECS" => self.hub_idle_secs = parse_var(&key, &value)?,
+        _ => {},
+      }
+    }
+    Ok(())
+  }
+
+  pub fn apply_args(&mut self, args: &ConfigArgs) {
+    if let Some(storage) = args.storage {
+      self.storage = storage;
+    }
+    if let Some(bind) = args.bind {
+      self.server.bind = bind;
+    }
+    if let Some(contact_points) = &args.contact_points {
+      self.cassandra.contact_points = split_list(contact_points);
+    }
+    if let Some(keyspace) = &args.keyspace {
+      self.cassandra.keyspace = keyspace.clone();
+    }
+    if let Some(length) = args.max_message_body_length {
+      self.max_message_body_length = length;
+    }
+  }
+
+  pub fn validate(&self) -> Result<()> {
+    let cassandra = &self.cassandra;
+    if self.storage == Storage::Cassandra {
+      if cassandra.contact_points.is_empty() {
+        return invalid("cassandra.contact_points must list at least one host");
+      }
+      if !KEYSPACE_REGEX.is_match(&cassandra.keyspace) {
+        return invalid(&format!("cassandra.keyspace {:?} is not a valid keyspace name", cassandra.keyspace));
+      }
+      if !(1..=5).contains(&cassandra.protocol_version) {
+        return invalid(&format!("cassandra.protocol_version {} is not supported", cassandra.protocol_version));
+      }
+      if cassandra.username.is_some() != cassandra.password.is_some() {
+        return invalid("cassandra.username and cassandra.password must be set together");
+      }
+    }
+
+    if self.channels.room_output == 0 || self.channels.user_output == 0 {
+      return invalid("channel sizes must be greater than zero");
+    }
+    if self.pages.messages < 1 || self.pages.rooms < 1 {
+      return invalid("page sizes must be greater than zero");
+    }
+    if self.heartbeat.interval_secs == 0 || self.heartbeat.timeout_secs <= self.heartbeat.interval_secs {
+      return invalid("heartbeat.timeout_secs must be longer than a non-zero heartbeat.interval_secs");
+    }
+    if matches!(&self.auth.secret, Some(secret) if secret.len() < MIN_AUTH_SECRET_LENGTH) {
+      return invalid(&format!("auth.secret must be at least {} bytes long", MIN_AUTH_SECRET_LENGTH));
+    }
+    if self.auth.token_ttl_secs == 0 {
+      return invalid("auth.token_ttl_secs must be greater than zero");
+    }
+    if self.max_message_body_length == 0 {
+      return invalid("max_message_body_length must be greater than zero");
+    }
+    if self.feed_capacity == 0 {
+      return invalid("feed_capacity must be greater than zero");
+    }
+    if self.hub_idle_secs == 0 {
+      return invalid("hub_idle_secs must be greater than zero");
+    }
+    Ok(())
+  }
+}
+
+fn invalid(message: &str) -> Result<()> {
+  Err(Error::Config(message.to_string()))
+}
+
+fn parse_var<T>(key: &str, value: &str) -> Result<T>
+where
+  T: FromStr,
+  T::Err: Display,
+{
+  value.parse::<T>()
+    .map_err(|err| Error::Config(format!("{}={:?}: {}", key, value, err)))
+}
+
+fn split_list(value: &str) -> Vec<String> {
+  value.split(',')
+    .map(|item| item.trim())
+    .filter(|item| !item.is_empty())
+    .map(String::from)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test001_file_values_override_defaults() {
+    let config = Config::from_toml(r#"
+      storage = "memory"
+      max_message_body_length = 512
+
+      [server]
+      bind = "0.0.0.0:9000"
+
+      [cassandra]
+      contact_points = ["10.0.0.1", "10.0.0.2"]
+    "#).unwrap();
+
+    assert_eq!(config.storage, Storage::Memory);
+    assert_eq!(config.max_message_body_length, 512);
+    assert_eq!(config.server.bind, "0.0.0.0:9000".parse().unwrap());
+    assert_eq!(config.channels, Config::default().channels);
+    assert_eq!(config.cassandra.contact_points, vec!["10.0.0.1", "10.0.0.2"]);
+    assert_eq!(config.cassandra.keyspace, "chat_app");
+  }
+
+  #[test]
+  fn test002_unknown_keys_are_rejected() {
+    assert!(Config::from_toml("max_body = 10").is_err());
+  }
+
+  #[test]
+  fn test003_env_and_args_override_file() {
+    let mut config = Config::default();
+    config.apply_env(vec![
+      ("CHAT_CASSANDRA_CONTACT_POINTS".to_string(), "a, b".to_string()),
+      ("CHAT_PAGES_MESSAGES".to_string(), "30".to_string()),
+      ("CHAT_FEED_CAPACITY".to_string(), "50".to_string()),
+      ("HOME".to_string(), "/root".to_string()),
+    ]).unwrap();
+
+    let args = ConfigArgs { keyspace: Some("chat_test".to_string()), ..Default::default() };
+    config.apply_args(&args);
+
+    assert_eq!(config.cassandra.contact_points, vec!["a", "b"]);
+    assert_eq!(config.pages.messages, 30);
+    assert_eq!(config.feed_capacity, 50);
+    assert_eq!(config.cassandra.keyspace, "chat_test");
+    assert!(config.apply_env(vec![("CHAT_PAGES_ROOMS".to_string(), "ten".to_string())]).is_err());
+  }
+
+  #[test]
+  fn test004_validate() {
+    let mut config = Config { storage: Storage::Cassandra, ..Default::default() };
+    assert!(config.validate().is_ok());
+
+    config.cassandra.keyspace = "chat-app".to_string();
+    assert!(config.validate().is_err());
+
+    let mut config = Config { storage: Storage::Cassandra, ..Default::default() };
+    config.cassandra.username = Some("chat".to_string());
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.channels.room_output = 0;
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.heartbeat.timeout_secs = config.heartbeat.interval_secs;
+    assert!(config.validate().is_err());
+
+    let mut config = Config::default();
+    config.apply_env(vec![("CHAT_AUTH_SECRET".to_string(), "too short".to_string())]).unwrap();
+    assert!(config.validate().is_err());
+    config.auth.secret = Some("x".repeat(32));
+    assert!(config.validate().is_ok());
+  }
+}
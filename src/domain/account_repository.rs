@@ -0,0 +1,16 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::model::account::Account;
+
+#[async_trait]
+pub trait AccountRepository: Send + Sync {
+
+    /// Stores a new account; fails with `Error::Conflict` when its username is taken.
+    async fn create_account(&self, account: Account) -> Result<Account>;
+
+    async fn load_account(&self, id: Uuid) -> Option<Account>;
+
+    async fn load_by_username(&self, username: &str) -> Option<Account>;
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::domain::account_repository::AccountRepository;
+use crate::domain::memory::MemoryDatabase;
+use crate::error::{Error, Result};
+use crate::model::account::Account;
+
+pub struct MemoryAccountRepository {
+    pub(crate) database: Arc<MemoryDatabase>
+}
+
+#[async_trait]
+impl AccountRepository for MemoryAccountRepository {
+
+    async fn create_account(&self, account: Account) -> Result<Account> {
+        // checked under the write lock, like the Cassandra lightweight transaction
+        let mut accounts = self.database.accounts.write().await;
+        if accounts.values().any(|existing| existing.username == account.username) {
+            return Err(Error::Conflict(format!("username {} is taken", account.username)));
+        }
+
+        accounts.insert(account.id, account.clone());
+        Ok(account)
+    }
+
+    async fn load_account(&self, id: Uuid) -> Option<Account> {
+        self.database.accounts.read().await.get(&id).cloned()
+    }
+
+    async fn load_by_username(&self, username: &str) -> Option<Account> {
+        self.database.accounts.read().await
+            .values()
+            .find(|account| account.username == username)
+            .cloned()
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::domain::memory::MemoryDatabase;
+use crate::domain::message_repository::MessageRepository;
+use crate::error::Result;
+use crate::model::message::Message;
+
+pub struct MemoryMessageRepository {
+    pub(crate) database: Arc<MemoryDatabase>
+}
+
+#[async_trait]
+impl MessageRepository for MemoryMessageRepository {
+
+    async fn add_new_message(&self, room_id: &str, message: Message) -> Option<Message> {
+        let mut persistent_msg = message.clone();
+        persistent_msg.room_id = room_id.to_string();
+
+        self.database.messages.write().await.push(persistent_msg);
+        Some(message)
+    }
+
+    async fn update_message_body(&self, room_id: &str, msg_id: Uuid, body: &str, edited_at: DateTime<Utc>) -> Option<Message> {
+        let mut messages = self.database.messages.write().await;
+        let message = messages
+            .iter_mut()
+            .find(|msg| msg.id == msg_id && msg.room_id == room_id)?;
+        message.body = body.to_string();
+        message.edited_at = Some(edited_at);
+        Some(message.clone())
+    }
+
+    async fn load_messages_by_room(&self, room_id: &str, before: Option<Uuid>, limit: i32) -> Option<Vec<Message>> {
+        let messages = self.database.messages.read().await;
+        let before = before.map(|id| Message::id_order(&id));
+
+        // newest first, like the clustering order of the room_message table
+        let room_messages = messages
+            .iter()
+            .rev()
+            .filter(|msg| msg.room_id == room_id)
+            .filter(|msg| before.iter().all(|before| Message::id_order(&msg.id) < *before))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect();
+
+        Some(room_messages)
+    }
+
+    async fn load_one_message(&self, room_id: &str, msg_id: Uuid) -> Option<Message> {
+        self.database.messages.read().await
+            .iter()
+            .find(|msg| msg.id == msg_id && msg.room_id == room_id)
+            .cloned()
+    }
+
+    async fn check_message_exists(&self, room_id: &str, msg_id: Uuid) -> bool {
+        self.database.messages.read().await
+            .iter()
+            .any(|msg| msg.id == msg_id && msg.room_id == room_id)
+    }
+
+    async fn delete_message(&self, room_id: &str, msg_id: Uuid) -> Result<()> {
+        self.database.messages.write().await
+            .retain(|msg| !(msg.id == msg_id && msg.room_id == room_id));
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::model::account::Account;
+use crate::model::message::Message;
+use crate::model::room::Room;
+use crate::model::room_user::RoomUser;
+use crate::model::user::User;
+
+pub mod room_repository;
+pub mod room_user_repository;
+pub mod user_repository;
+pub mod message_repository;
+pub mod account_repository;
+
+/// Tables shared by the in-memory repositories, mirroring the Cassandra schema in `cql/`.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    pub(crate) rooms: RwLock<HashMap<String, Room>>,
+    pub(crate) users: RwLock<Vec<(User, DateTime<Utc>)>>,
+    pub(crate) messages: RwLock<Vec<Message>>,
+    pub(crate) room_users: RwLock<BTreeMap<(String, Uuid), RoomUser>>,
+    pub(crate) accounts: RwLock<HashMap<Uuid, Account>>,
+}
+
+impl MemoryDatabase {
+
+    pub fn new() -> Arc<Self> {
+        Arc::new(MemoryDatabase::default())
+    }
+}
+
+pub(crate) fn paginate<T>(items: impl Iterator<Item = T>, page: i32, size: i32) -> Vec<T> {
+    if page < 1 || size < 1 {
+        return vec![];
+    }
+
+    items
+        .skip(((page - 1) * size) as usize)
+        .take(size as usize)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::account_repository::AccountRepository;
+    use crate::domain::memory::account_repository::MemoryAccountRepository;
+    use crate::domain::memory::message_repository::MemoryMessageRepository;
+    use crate::domain::memory::room_repository::MemoryRoomRepository;
+    use crate::domain::memory::room_user_repository::MemoryRoomUserRepository;
+    use crate::domain::memory::user_repository::MemoryUserRepository;
+    use crate::domain::message_repository::MessageRepository;
+    use crate::domain::room_repository::RoomRepository;
+    use crate::domain::room_user_repository::RoomUserRepository;
+    use crate::domain::user_repository::UserRepository;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test001_room_and_host_are_shared_between_repositories() {
+        let database = MemoryDatabase::new();
+        let room_repo = MemoryRoomRepository { database: Arc::clone(&database) };
+        let user_repo = MemoryUserRepository { database: Arc::clone(&database) };
+
+        let host_id = Uuid::new_v4();
+        let guest = User::new(Uuid::new_v4(), "guest");
+        let room = Room::new("room".to_string(), "title".to_string(), host_id, "host".to_string(),
+                             Some(vec![guest.clone()]), Utc::now(), "key".to_string());
+
+        assert!(aw!(room_repo.create_room(room)).is_some());
+        assert!(aw!(room_repo.room_exists("room")));
+        assert_eq!(aw!(user_repo.load_host_room("room")).unwrap().id, host_id);
+        assert_eq!(aw!(user_repo.load_participants_room("room")), Some(vec![guest]));
+
+        aw!(room_repo.delete_room("room")).unwrap();
+        assert!(!aw!(room_repo.room_exists("room")));
+        assert!(aw!(user_repo.load_host_room("room")).is_none());
+    }
+
+    #[test]
+    fn test002_users_are_created_once() {
+        let user_repo = MemoryUserRepository { database: MemoryDatabase::new() };
+        let alice = User::new(Uuid::new_v4(), "alice");
+
+        assert!(aw!(user_repo.create_user(alice.clone())).is_some());
+        assert!(aw!(user_repo.create_user(alice)).is_none());
+        assert!(aw!(user_repo.create_user(User::new(Uuid::new_v4(), "alice"))).is_some());
+        assert_eq!(aw!(user_repo.load_users(1, 10)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test003_messages_are_paged_newest_first() {
+        let msg_repo = MemoryMessageRepository { database: MemoryDatabase::new() };
+        let alice = User::new(Uuid::new_v4(), "alice");
+        let bob = User::new(Uuid::new_v4(), "bob");
+        let carol = User::new(Uuid::new_v4(), "carol");
+
+        for (user, body) in &[(&alice, "first"), (&bob, "second"), (&carol, "third")] {
+            aw!(msg_repo.add_new_message("room", Message::new((*user).clone(), "room", body)));
+        }
+        aw!(msg_repo.add_new_message("other", Message::new(alice.clone(), "other", "elsewhere")));
+
+        let page = aw!(msg_repo.load_messages_by_room("room", None, 2)).unwrap();
+        let bodies: Vec<&str> = page.iter().map(|msg| msg.body.as_str()).collect();
+        assert_eq!(bodies, vec!["third", "second"]);
+        assert!(page[0].created_at >= page[1].created_at);
+
+        let page = aw!(msg_repo.load_messages_by_room("room", Some(page[1].id), 2)).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].from, alice);
+
+        let msg_id = page[0].id;
+        assert!(aw!(msg_repo.load_one_message("other", msg_id)).is_none());
+        let edited = aw!(msg_repo.update_message_body("room", msg_id, "edited", Utc::now())).unwrap();
+        assert_eq!(edited.body, "edited");
+        assert!(edited.edited_at.is_some());
+
+        aw!(msg_repo.delete_message("room", msg_id)).unwrap();
+        assert!(!aw!(msg_repo.check_message_exists("room", msg_id)));
+    }
+
+    #[test]
+    fn test004_room_users_by_room_and_user() {
+        let room_user_repo = MemoryRoomUserRepository { database: MemoryDatabase::new() };
+        let user_id = Uuid::new_v4();
+
+        for room_id in &["first", "second"] {
+            aw!(room_user_repo.create_room_users(RoomUser::new(
+                room_id.to_string(), "title".to_string(), user_id, "alice".to_string(), Utc::now())));
+        }
+
+        assert_eq!(aw!(room_user_repo.load_by_userid(user_id, 1, 10)).unwrap().len(), 2);
+        assert_eq!(aw!(room_user_repo.load_by_room("first".to_string())).unwrap().len(), 1);
+
+        let alice = User::new(user_id, "alice");
+        let older = Message::new(alice.clone(), "first", "older").id;
+        let newer = Message::new(alice, "first", "newer").id;
+        assert_eq!(aw!(room_user_repo.advance_last_read("first".to_string(), user_id, newer)), Some(true));
+        assert_eq!(aw!(room_user_repo.advance_last_read("first".to_string(), user_id, older)), Some(false));
+        assert_eq!(aw!(room_user_repo.load_one("first".to_string(), user_id)).unwrap().last_read, Some(newer));
+        assert!(aw!(room_user_repo.advance_last_read("third".to_string(), user_id, newer)).is_none());
+        assert!(aw!(room_user_repo.load_one("third".to_string(), user_id)).is_none());
+
+        aw!(room_user_repo.delete_by_room("first".to_string())).unwrap();
+        assert!(aw!(room_user_repo.load_by_room("first".to_string())).unwrap().is_empty());
+        assert_eq!(aw!(room_user_repo.load_by_userid(user_id, 1, 10)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test005_usernames_are_unique() {
+        let account_repo = MemoryAccountRepository { database: MemoryDatabase::new() };
+        let alice = aw!(account_repo.create_account(Account::new("alice", "hash".to_string(), "Alice"))).unwrap();
+
+        match aw!(account_repo.create_account(Account::new("alice", "other".to_string(), "Impostor"))) {
+            Err(crate::error::Error::Conflict(_)) => {},
+            _ => panic!("a second alice was stored"),
+        }
+        assert_eq!(aw!(account_repo.load_by_username("alice")), Some(alice.clone()));
+        assert_eq!(aw!(account_repo.load_account(alice.id)).unwrap().display_name, "Alice");
+    }
+}
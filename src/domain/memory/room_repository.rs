@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::domain::memory::MemoryDatabase;
+use crate::domain::room_repository::RoomRepository;
+use crate::error::Result;
+use crate::model::room::Room;
+use crate::model::user::User;
+
+pub struct MemoryRoomRepository {
+    pub(crate) database: Arc<MemoryDatabase>
+}
+
+#[async_trait]
+impl RoomRepository for MemoryRoomRepository {
+
+    async fn create_room(&self, room: Room) -> Option<Room> {
+        let mut rooms = self.database.rooms.write().await;
+        if rooms.contains_key(room.room_id.as_str()) {
+            return None;
+        }
+        rooms.insert(room.room_id.clone(), room.clone());
+        Some(room)
+    }
+
+    async fn update_participant_in_room(&self, room_id: &str, participants: Vec<User>) -> Result<()> {
+        if let Some(room) = self.database.rooms.write().await.get_mut(room_id) {
+            room.participants = Some(participants);
+        }
+        Ok(())
+    }
+
+    async fn load_rooms(&self) -> Vec<Room> {
+        self.database.rooms.read().await.values().cloned().collect()
+    }
+
+    async fn load_one_room(&self, room_id: &str) -> Option<Room> {
+        self.database.rooms.read().await.get(room_id).cloned()
+    }
+
+    async fn room_exists(&self, room_id: &str) -> bool {
+        self.database.rooms.read().await.contains_key(room_id)
+    }
+
+    async fn delete_room(&self, room_id: &str) -> Result<()> {
+        self.database.rooms.write().await.remove(room_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::domain::memory::{paginate, MemoryDatabase};
+use crate::domain::room_user_repository::RoomUserRepository;
+use crate::error::Result;
+use crate::model::message::Message;
+use crate::model::room_user::RoomUser;
+
+pub struct MemoryRoomUserRepository {
+    pub(crate) database: Arc<MemoryDatabase>
+}
+
+#[async_trait]
+impl RoomUserRepository for MemoryRoomUserRepository {
+
+    async fn create_room_users(&self, input: RoomUser) -> Option<RoomUser> {
+        self.database.room_users.write().await
+            .insert((input.room_id.clone(), input.user_id), input.clone());
+        Some(input)
+    }
+
+    async fn load_by_userid(&self, user_id: Uuid, page: i32, size: i32) -> Option<Vec<RoomUser>> {
+        let room_users = self.database.room_users.read().await;
+        let rooms = room_users
+            .values()
+            .filter(|room_user| room_user.user_id == user_id)
+            .cloned();
+
+        Some(paginate(rooms, page, size))
+    }
+
+    async fn load_by_room(&self, room_id: String) -> Option<Vec<RoomUser>> {
+        Some(
+            self.database.room_users.read().await
+                .values()
+                .filter(|room_user| room_user.room_id == room_id)
+                .cloned()
+                .collect()
+        )
+    }
+
+    async fn load_one(&self, room_id: String, user_id: Uuid) -> Option<RoomUser> {
+        self.database.room_users.read().await.get(&(room_id, user_id)).cloned()
+    }
+
+    async fn advance_last_read(&self, room_id: String, user_id: Uuid, msg_id: Uuid) -> Option<bool> {
+        // compared under the write lock, like the Cassandra lightweight transaction
+        let mut room_users = self.database.room_users.write().await;
+        let room_user = room_users.get_mut(&(room_id, user_id))?;
+        if matches!(room_user.last_read, Some(last_read) if Message::id_order(&last_read) >= Message::id_order(&msg_id)) {
+            return Some(false);
+        }
+        room_user.last_read = Some(msg_id);
+        Some(true)
+    }
+
+    async fn delete_room_user(&self, room_id: String, user_id: Uuid) -> Result<()> {
+        self.database.room_users.write().await.remove(&(room_id, user_id));
+        Ok(())
+    }
+
+    async fn delete_by_room(&self, room_id: String) -> Result<()> {
+        self.database.room_users.write().await
+            .retain(|(id, _), _| *id != room_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::domain::memory::{paginate, MemoryDatabase};
+use crate::domain::user_repository::UserRepository;
+use crate::error::Result;
+use crate::model::user::User;
+
+pub struct MemoryUserRepository {
+    pub(crate) database: Arc<MemoryDatabase>
+}
+
+#[async_trait]
+impl UserRepository for MemoryUserRepository {
+
+    async fn create_user(&self, user: User) -> Option<User> {
+        // checked under the write lock, so concurrent creates cannot both pass
+        let mut users = self.database.users.write().await;
+        if users.iter().any(|(existing, _)| existing.id == user.id) {
+            return None;
+        }
+
+        users.push((user.clone(), Utc::now()));
+        Some(user)
+    }
+
+    async fn load_users(&self, page: i32, size: i32) -> Option<Vec<User>> {
+        let mut users = self.database.users.read().await.clone();
+        users.sort_by(|(_, left), (_, right)| right.cmp(left));
+        Some(paginate(users.into_iter().map(|(user, _)| user), page, size))
+    }
+
+    async fn load_one_user(&self, id: Uuid) -> Option<User> {
+        self.database.users.read().await
+            .iter()
+            .find(|(user, _)| user.id == id)
+            .map(|(user, _)| user.clone())
+    }
+
+    async fn load_host_room(&self, room_id: &str) -> Option<User> {
+        self.database.rooms.read().await
+            .get(room_id)
+            .map(|room| room.host_info.clone())
+    }
+
+    async fn load_participants_room(&self, room_id: &str) -> Option<Vec<User>> {
+        self.database.rooms.read().await
+            .get(room_id)
+            .map(|room| room.participants.clone().unwrap_or_default())
+    }
+
+    async fn user_exists(&self, user: User) -> bool {
+        self.database.users.read().await
+            .iter()
+            .any(|(existing, _)| existing.name == user.name)
+    }
+
+    async fn delete_user(&self, id: Uuid) -> Result<()> {
+        self.database.users.write().await.retain(|(user, _)| user.id != id);
+        Ok(())
+    }
+}
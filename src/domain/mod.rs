@@ -3,3 +3,5 @@ pub mod room_repository;
 pub mod room_user_repository;
 pub mod user_repository;
 pub mod message_repository;
+pub mod account_repository;
+pub mod memory;
@@ -1,108 +1,231 @@
-use std::any::Any;
-use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
-use async_trait::async_trait;
-use cassandra_cpp::*;
-use chrono::{NaiveDateTime, DateTime, Utc};
-use uuid::Uuid;
-use tokio::sync::Mutex;
+use serde::Deserialize;
+#[cfg(feature = "cassandra")]
+use cassandra_cpp::Session;
 
-use crate::model::user::User;
+use crate::config::Config;
+use crate::error::{Error, Result};
 use crate::domain::room_repository::RoomRepository;
 use crate::domain::room_user_repository::RoomUserRepository;
 use crate::domain::user_repository::UserRepository;
 use crate::domain::message_repository::MessageRepository;
+use crate::domain::account_repository::AccountRepository;
+use crate::domain::memory::MemoryDatabase;
+use crate::domain::memory::room_repository::MemoryRoomRepository;
+use crate::domain::memory::room_user_repository::MemoryRoomUserRepository;
+use crate::domain::memory::user_repository::MemoryUserRepository;
+use crate::domain::memory::message_repository::MemoryMessageRepository;
+use crate::domain::memory::account_repository::MemoryAccountRepository;
+#[cfg(feature = "cassandra")]
+use crate::cass::{
+    server_node::ServerNode,
+    statement_cache::StatementCache,
+    room_repository::CassRoomRepository,
+    room_user_repository::CassRoomUserRepository,
+    user_repository::CassUserRepository,
+    message_repository::CassMessageRepository,
+    account_repository::CassAccountRepository,
+};
+
+/// Storage backend the repositories are built on.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    Memory,
+    Cassandra,
+}
 
-#[derive(Default)]
-pub struct RepositoryFactory(HashMap<String, Arc<dyn Any>>);
-
-impl RepositoryFactory {
-
-    pub fn new() -> Self {
-        RepositoryFactory(HashMap::<String, Arc<dyn Any>>::new())
-    }
-
-    pub fn add_repository(&mut self, cluster: Cluster, kind: RepoKind) {
-        let (key, repo): (&str, Arc<dyn Any>) = match kind {
-            RepoKind::ROOM => (
-                "ROOM",
-                Arc::new(RoomRepository {
-                    cluster: Mutex::new(cluster)
-                })
-            ),
-            RepoKind::USER => (
-                "USER",
-                Arc::new(UserRepository {
-                    cluster: Mutex::new(cluster)
-                })
-            ),
-            RepoKind::ROOM_USERS => (
-                "ROOM_USERS",
-                Arc::new(RoomUserRepository {
-                    cluster: Mutex::new(cluster)
-                })
-            ),
-            RepoKind::MESSAGE => (
-                "MESSAGE", 
-                Arc::new(MessageRepository {
-                    cluster: Mutex::new(cluster)
-                })
-            ),
-        };
-        self.0.insert(key.to_string(), repo);
-    }
-
-    pub fn get_repository(&self, repo_name: &str) -> Arc<dyn Any> {
-        match self.0.get(repo_name) {
-            Some(repo) => Arc::clone(repo),
-            None => panic!("Not supported")
+impl Default for Storage {
+    fn default() -> Self {
+        if cfg!(feature = "cassandra") {
+            Storage::Cassandra
+        } else {
+            Storage::Memory
         }
     }
 }
 
-pub enum RepoKind {
-    ROOM,
-    USER,
-    ROOM_USERS,
-    MESSAGE,
+impl FromStr for Storage {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "memory" => Ok(Storage::Memory),
+            "cassandra" => Ok(Storage::Cassandra),
+            _ => Err(Error::System(format!("unknown storage backend: {}", value))),
+        }
+    }
 }
 
-#[async_trait]
-pub trait Repository {
+/// Complete set of repositories shared by the storages and hubs.
+#[derive(Clone)]
+pub struct Repositories {
+    room: Arc<dyn RoomRepository>,
+    user: Arc<dyn UserRepository>,
+    message: Arc<dyn MessageRepository>,
+    room_user: Arc<dyn RoomUserRepository>,
+    account: Arc<dyn AccountRepository>,
+}
+
+impl Repositories {
+
+    pub fn builder() -> RepositoriesBuilder {
+        RepositoriesBuilder::default()
+    }
+
+    /// Connects the chosen backend; for Cassandra this opens the one session all repositories share.
+    pub async fn connect(config: &Config) -> Result<Self> {
+        match config.storage {
+            Storage::Memory => Ok(Self::in_memory()),
+            #[cfg(feature = "cassandra")]
+            Storage::Cassandra => {
+                let mut node = ServerNode::with_config(config.cassandra.clone());
+                node.init().await?;
+                match node.session() {
+                    Some(session) => Ok(Self::cassandra(session)),
+                    None => Err(Error::System("cassandra session is not available".to_string())),
+                }
+            },
+            #[cfg(not(feature = "cassandra"))]
+            Storage::Cassandra => Err(Error::System("cassandra storage is not compiled in".to_string())),
+        }
+    }
+
+    /// Builds every repository against one shared in-memory database.
+    pub fn in_memory() -> Self {
+        let database = MemoryDatabase::new();
+        Repositories {
+            room: Arc::new(MemoryRoomRepository { database: Arc::clone(&database) }),
+            user: Arc::new(MemoryUserRepository { database: Arc::clone(&database) }),
+            message: Arc::new(MemoryMessageRepository { database: Arc::clone(&database) }),
+            room_user: Arc::new(MemoryRoomUserRepository { database: Arc::clone(&database) }),
+            account: Arc::new(MemoryAccountRepository { database }),
+        }
+    }
+
+    /// Cassandra repositories sharing one session and one prepared statement cache.
+    #[cfg(feature = "cassandra")]
+    pub fn cassandra(session: Arc<Session>) -> Self {
+        let statements = Arc::new(StatementCache::new(session));
+        Repositories {
+            room: Arc::new(CassRoomRepository { statements: Arc::clone(&statements) }),
+            user: Arc::new(CassUserRepository { statements: Arc::clone(&statements) }),
+            message: Arc::new(CassMessageRepository { statements: Arc::clone(&statements) }),
+            room_user: Arc::new(CassRoomUserRepository { statements: Arc::clone(&statements) }),
+            account: Arc::new(CassAccountRepository { statements }),
+        }
+    }
+
+    pub fn room(&self) -> Arc<dyn RoomRepository> {
+        Arc::clone(&self.room)
+    }
+
+    pub fn user(&self) -> Arc<dyn UserRepository> {
+        Arc::clone(&self.user)
+    }
+
+    pub fn message(&self) -> Arc<dyn MessageRepository> {
+        Arc::clone(&self.message)
+    }
+
+    pub fn room_user(&self) -> Arc<dyn RoomUserRepository> {
+        Arc::clone(&self.room_user)
+    }
 
-    async fn retrieve_session(&self) -> Result<Session>;
+    pub fn account(&self) -> Arc<dyn AccountRepository> {
+        Arc::clone(&self.account)
+    }
 }
 
-pub struct Utils {}
+/// Assembles a `Repositories` set from individual backends, e.g. to mix storages in tests.
+#[derive(Default)]
+pub struct RepositoriesBuilder {
+    room: Option<Arc<dyn RoomRepository>>,
+    user: Option<Arc<dyn UserRepository>>,
+    message: Option<Arc<dyn MessageRepository>>,
+    room_user: Option<Arc<dyn RoomUserRepository>>,
+    account: Option<Arc<dyn AccountRepository>>,
+}
 
-impl Utils {
+impl RepositoriesBuilder {
 
-    pub const SEPARATOR_CHARS: &'static str = "*&&*";
+    pub fn room(mut self, repository: Arc<dyn RoomRepository>) -> Self {
+        self.room = Some(repository);
+        self
+    }
 
-    pub fn from_uuid_to_cass_uuid(uuid: Uuid) -> cassandra_cpp::Uuid {
-        cassandra_cpp::Uuid::from_str( uuid.to_string().as_str() ).ok().unwrap()
+    pub fn user(mut self, repository: Arc<dyn UserRepository>) -> Self {
+        self.user = Some(repository);
+        self
     }
 
-    pub fn from_cass_uuid_to_uuid(cass_uuid: cassandra_cpp::Uuid) -> Uuid {
-        Uuid::from_str(cass_uuid.to_string().as_str()).ok().unwrap()
+    pub fn message(mut self, repository: Arc<dyn MessageRepository>) -> Self {
+        self.message = Some(repository);
+        self
     }
 
-    pub fn from_timestamp_to_datetime(timestamp: i64) -> DateTime<Utc> {
-        let datetime = NaiveDateTime::from_timestamp(timestamp, 0);
-        DateTime::from_utc(datetime, Utc)
+    pub fn room_user(mut self, repository: Arc<dyn RoomUserRepository>) -> Self {
+        self.room_user = Some(repository);
+        self
     }
 
-    pub fn get_participants(participants: SetIterator) -> Option<Vec<User>> {
-        Some(
-            participants.map(|participant| {
-                let part_record = Result::ok(participant.get_string()).unwrap();
-                let items: Vec<&str> = part_record.split(Self::SEPARATOR_CHARS).collect();
-                User {
-                    id: Uuid::from_str(items.get(0).unwrap()).unwrap(),
-                    name: items.get(1).unwrap().to_string(),
-                }
-            }).collect()
-        )
+    pub fn account(mut self, repository: Arc<dyn AccountRepository>) -> Self {
+        self.account = Some(repository);
+        self
     }
-}
\ No newline at end of file
+
+    /// Fails with the names of all repositories that were never registered.
+    pub fn build(self) -> Result<Repositories> {
+        match (self.room, self.user, self.message, self.room_user, self.account) {
+            (Some(room), Some(user), Some(message), Some(room_user), Some(account)) => Ok(Repositories {
+                room,
+                user,
+                message,
+                room_user,
+                account,
+            }),
+            (room, user, message, room_user, account) => {
+                let missing: Vec<&str> = vec![
+                    ("room", room.is_none()),
+                    ("user", user.is_none()),
+                    ("message", message.is_none()),
+                    ("room_user", room_user.is_none()),
+                    ("account", account.is_none()),
+                ]
+                    .into_iter()
+                    .filter(|(_, is_missing)| *is_missing)
+                    .map(|(name, _)| name)
+                    .collect();
+
+                Err(Error::System(format!("missing repositories: {}", missing.join(", "))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test001_builder_reports_missing_repositories() {
+        let memory = Repositories::in_memory();
+        let result = Repositories::builder()
+            .room(memory.room())
+            .message(memory.message())
+            .build();
+
+        match result {
+            Err(Error::System(message)) => assert_eq!(message, "missing repositories: user, room_user, account"),
+            _ => panic!("builder accepted an incomplete repository set"),
+        }
+    }
+
+    #[test]
+    fn test002_parse_storage() {
+        assert_eq!("memory".parse::<Storage>().unwrap(), Storage::Memory);
+        assert_eq!("cassandra".parse::<Storage>().unwrap(), Storage::Cassandra);
+        assert!("mysql".parse::<Storage>().is_err());
+    }
+}
@@ -5,6 +5,9 @@ use serde::export::Formatter;
 #[derive(Debug)]
 pub enum Error {
   System(String),
+  Config(String),
+  Forbidden(String),
+  Conflict(String),
   Io(io::Error),
   Message(serde_json::Error),
 }
@@ -13,6 +16,9 @@ impl fmt::Display for Error {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match self {
       Error::System(err) => write!(f, "system error: {}", err),
+      Error::Config(err) => write!(f, "configuration error: {}", err),
+      Error::Forbidden(err) => write!(f, "forbidden: {}", err),
+      Error::Conflict(err) => write!(f, "conflict: {}", err),
       Error::Io(ref err) => write!(f, "IO error: {}", err),
       Error::Message(ref err) => write!(f, "Invalid message: {}", err),
     }
@@ -33,4 +39,11 @@ impl From<serde_json::Error> for Error {
   }
 }
 
+#[cfg(feature = "cassandra")]
+impl From<cassandra_cpp::Error> for Error {
+  fn from(err: cassandra_cpp::Error) -> Self {
+    Error::System(err.to_string())
+  }
+}
+
 pub type Result<T> = result::Result<T, Error>;
@@ -2,52 +2,92 @@ use std::sync::Arc;
 use uuid::Uuid;
 // use regex::Regex;
 use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
 use tokio::time::Duration;
-use tokio::sync::{broadcast, RwLock};
-// use chrono::Utc;
+use tokio::sync::RwLock;
+use chrono::Utc;
+use log::error;
 
 use crate::proto::*;
+use crate::session::SessionRegistry;
+use crate::presence::PresenceService;
 use crate::model::{user::User, feed::Feed, message::Message};
 use crate::domain::message_repository::MessageRepository;
-use crate::domain::user_repository::UserRepository;
 use crate::domain::room_user_repository::RoomUserRepository;
-use crate::model::room_user::RoomUser;
+use crate::domain::user_repository::UserRepository;
 
 // const OUTPUT_CHANNEL_SIZE: usize = 16;
-const MAX_MESSAGE_BODY_LENGTH: usize = 256;
-const DEFAULT_PAGE_SIZE: i32 = 15;
+const MAX_HISTORY_LIMIT: i32 = 100;
+
 // lazy_static! {
 //   static ref USER_NAME_REGEX: Regex = Regex::new("[A-Za-z\\s]{4,24}").unwrap();
 // }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy)]
 pub struct HubOptions {
   pub alive_interval: Option<Duration>,
+  pub max_message_body_length: usize,
+  pub page_size: i32,
+  pub feed_capacity: usize,
+  /// How long a typing indicator lasts without a new `typing-started`.
+  pub typing_timeout: Duration,
+  /// Shortest time between two relayed `typing-started` of the same user.
+  pub typing_rate: Duration,
+}
+
+impl Default for HubOptions {
+  fn default() -> Self {
+    HubOptions {
+      alive_interval: None,
+      max_message_body_length: 256,
+      page_size: 15,
+      feed_capacity: 100,
+      typing_timeout: Duration::from_secs(6),
+      typing_rate: Duration::from_secs(2),
+    }
+  }
+}
+
+#[derive(Default)]
+struct Typing {
+  // users typing, and until when
+  expires: HashMap<Uuid, Instant>,
+  // when the last typing-started of each user was relayed
+  relayed: HashMap<Uuid, Instant>,
 }
 
 pub struct Hub {
-  output_sender: broadcast::Sender<OutputParcel>,
+  sessions: Arc<SessionRegistry>,
+  presence: Arc<PresenceService>,
   users: RwLock<HashMap<Uuid, User>>,
   feed: RwLock<Feed>,
+  typing: Mutex<Typing>,
+  options: HubOptions,
 
-  user_repo: Arc<UserRepository>,
-  msg_repo: Arc<MessageRepository>,
-  room_user_repo: Arc<RoomUserRepository>,
+  user_repo: Arc<dyn UserRepository>,
+  room_user_repo: Arc<dyn RoomUserRepository>,
+  msg_repo: Arc<dyn MessageRepository>,
 }
 
 impl Hub {
-  pub fn new(output_sender: broadcast::Sender<OutputParcel>,
-             user_repo: Arc<UserRepository>,
-             msg_repo: Arc<MessageRepository>,
-             room_user_repo: Arc<RoomUserRepository>) -> Self {
+  pub fn new(sessions: Arc<SessionRegistry>,
+             presence: Arc<PresenceService>,
+             user_repo: Arc<dyn UserRepository>,
+             room_user_repo: Arc<dyn RoomUserRepository>,
+             msg_repo: Arc<dyn MessageRepository>,
+             options: HubOptions) -> Self {
     // let (output_sender, _) = broadcast::channel(OUTPUT_CHANNEL_SIZE);
     Hub {
-      output_sender,
+      sessions,
+      presence,
       users: Default::default(),
-      feed: Default::default(),
+      feed: RwLock::new(Feed::new(options.feed_capacity)),
+      typing: Default::default(),
+      options,
       user_repo,
-      msg_repo,
       room_user_repo,
+      msg_repo,
     }
   }
 
@@ -64,35 +104,21 @@ impl Hub {
   // }
 
   fn send_room(&self, room_id: &str, output: Output) {
-    if self.output_sender.receiver_count() > 0 {
-      self.output_sender
-          .send(OutputParcel::new(String::from(room_id), Uuid::default(), output))
-          .unwrap();
-    }
+    self.sessions.send(OutputParcel::new(String::from(room_id), Uuid::default(), output));
   }
 
   fn send_targeted(&self, room_id: &str, client_id: Uuid, output: Output) {
-    if self.output_sender.receiver_count() > 0 {
-      self.output_sender
-        .send(OutputParcel::new(String::from(room_id), client_id, output))
-        .unwrap();
-    }
+    self.sessions.send(OutputParcel::new(String::from(room_id), client_id, output));
   }
 
   async fn send_ignored(&self, room_id: &str, ignore_client_id: Uuid, output: Output) {
-    if self.output_sender.receiver_count() == 0 {
-      return;
-    }
-
     self.users
       .read()
       .await
       .values()
       .filter(|user| user.id != ignore_client_id)
       .for_each(|user| {
-        self.output_sender
-          .send(OutputParcel::new(String::from(room_id), user.id, output.clone()))
-          .unwrap();
+        self.sessions.send(OutputParcel::new(String::from(room_id), user.id, output.clone()));
       });
   }
 
@@ -100,80 +126,47 @@ impl Hub {
     self.send_targeted(room_id, client_id, Output::Error(error));
   }
 
-  pub fn subscribe(&self) -> broadcast::Receiver<OutputParcel> {
-    self.output_sender.subscribe()
-  }
-
   pub async fn on_disconnect(&self, room_id: &str, client_id: Uuid) {
+    self.clear_typing(client_id);
     if self.users.write().await.remove(&client_id).is_some() {
       self.send_ignored(room_id, client_id, Output::UserLeft(UserLeftOutput::new(String::from(room_id), client_id))).await
     }
   }
 
-  // async fn tick_alive(&self, room_id: &str) {
-  //   let alive_interval = if let Some(alive_interval) = self.alive_interval {
-  //     alive_interval
-  //   } else {
-  //     return;
-  //   };
-
-  //   loop {
-  //     time::delay_for(alive_interval).await;
-  //     self.send(room_id, Output::Alive).await;
-  //   }
-  // }
-
-  // pub async fn run(&self, receiver: UnboundedReceiver<InputParcel>) {
-  //   let ticking_alive = self.tick_alive();
-  //   let processing = receiver.for_each(|input_parcel| self.process(input_parcel));
-
-  //   tokio::select! {
-  //     _ = ticking_alive => {},
-  //     _ = processing => {},
-  //   }
-  // }
-
-  async fn load_room_user_except(&self, room_id: &str, from_id: Uuid) -> Option<RoomUser> {
-    if let Some(users) = self.room_user_repo.load_by_room(room_id.to_string()).await {
-      users.into_iter()
-          .filter(|user| user.user_id != from_id)
-          .last()
-    }
-    else {
-      None
+  /// Passes a presence change on to the room when the user is one of its members.
+  pub async fn on_presence_changed(&self, room_id: &str, presence: PresenceOutput) {
+    if self.users.read().await.contains_key(&presence.user_id) {
+      self.send_room(room_id, Output::PresenceChanged(presence));
     }
   }
-  
+
   pub async fn process(&self, input_parcel: InputParcel) {
     match input_parcel.input {
       Input::LoadRoom(input) => self.process_load(input_parcel.room_id.as_str(), input).await,
       Input::JoinRoom(input) => self.process_join(input_parcel.room_id.as_str(), input_parcel.client_id, input).await,
       Input::PostMessage(input) => self.process_post(input_parcel.room_id.as_str(), input_parcel.client_id, input).await,
-      _ => unimplemented!(),
+      Input::EditMessage(input) => self.process_edit(input_parcel.room_id.as_str(), input_parcel.client_id, input).await,
+      Input::DeleteMessage(input) => self.process_delete(input_parcel.room_id.as_str(), input_parcel.client_id, input).await,
+      Input::LoadHistory(input) => self.process_history(input_parcel.room_id.as_str(), input_parcel.client_id, input).await,
+      Input::TypingStarted(_) => self.process_typing(input_parcel.room_id.as_str(), input_parcel.client_id, true, Instant::now()).await,
+      Input::TypingStopped(_) => self.process_typing(input_parcel.room_id.as_str(), input_parcel.client_id, false, Instant::now()).await,
+      Input::MarkRead(input) => self.process_mark_read(input_parcel.room_id.as_str(), input_parcel.client_id, input).await,
+      _ => self.send_error(input_parcel.room_id.as_str(), input_parcel.client_id, OutputError::UnsupportedInput),
     }
   }
 
-  async fn process_load(&self, room_id: &str, load_room_input: LoadRoomInput) {
+  async fn process_load(&self, room_id: &str, _load_room_input: LoadRoomInput) {
 
     // load messages
     if self.feed.read().await.is_empty() {
 
-      // if feed empty, get to user from db
-      if let Some(to_user) = self.load_room_user_except(room_id, load_room_input.from_id).await {
-        let from_id = load_room_input.from_id;
-        let to_id = to_user.user_id;
-
-        // let check db and insert to feed if present
-        if let Some(msgs) = self.msg_repo
-            .load_messages_by_room(room_id, from_id, to_id, 1, DEFAULT_PAGE_SIZE).await {
-          let mut feed_write = self.feed.write().await;
-          msgs.iter().for_each(|msg| {
-            feed_write.add_message(msg.clone());
-          });
-        }
-      }
-      else {
-        return;
+      // let check db and insert to feed if present
+      if let Some(msgs) = self.msg_repo
+          .load_messages_by_room(room_id, None, self.options.page_size).await {
+        let mut feed_write = self.feed.write().await;
+        msgs.iter().for_each(|msg| {
+          feed_write.add_message(msg.clone());
+        });
       }
     }
 
@@ -202,28 +195,12 @@ impl Hub {
     // produce load room output
     let messages: Vec<MessageOutput> = self.feed.read().await
         .messages_iter()
-        .map(|msg| {
-          let msg = msg.clone();
-          MessageOutput {
-            id: msg.id,
-            user: UserOutput {
-              id: msg.from.id,
-              name: msg.from.name
-            },
-            body: msg.body,
-          }
-        })
+        .map(Self::message_output)
         .collect();
 
     let users = self.users.read().await
         .values()
-        .map(|user| {
-          let user = user.clone();
-          UserOutput {
-            id: user.id,
-            name: user.name
-          }
-        })
+        .map(|user| MemberOutput::new(user.id, &user.name, self.presence.presence(user.id)))
         .collect();
 
     self.send_room(room_id, Output::RoomLoaded(
@@ -280,14 +257,7 @@ impl Hub {
       .read()
       .await
       .messages_iter()
-      .map(|message| {
-        MessageOutput::new(
-          message.id,
-          UserOutput::new(message.from.id, &message.from.name),
-          &message.body,
-          // message.created_at,
-        )
-      })
+      .map(Self::message_output)
       .collect();
 
     self.send_targeted(
@@ -318,31 +288,628 @@ impl Hub {
       return;
     };
 
+    let message = match self.add_message(room_id, user, &input.body).await {
+      Ok(message) => message,
+      Err(error) => {
+        self.send_error(room_id, client_id, error);
+        return;
+      }
+    };
+
+    // report send message success
+    let message_output = Self::message_output(&message);
+
+    // report post status
+    self.send_targeted(room_id, client_id, Output::Posted(PostedOutput::new(message_output.clone())));
+
+    // notify everyone about new message
+    self.send_ignored(room_id, client_id, Output::UserPosted(UserPostedOutput::new(message_output))).await;
+
+    // a posted message ends the typing indicator
+    if self.clear_typing(client_id) {
+      self.send_typing(room_id, client_id, false).await;
+    }
+
+    // serve message
+    self.msg_repo.add_new_message(room_id, message).await;
+  }
+
+  /// Posts for a member who need not have joined, as the REST API does. Every socket in the
+  /// room gets `user-posted`, the author's own included.
+  pub async fn post_message(&self, room_id: &str, author: User, body: &str) -> Result<MessageOutput, OutputError> {
+    let author_id = author.id;
+    let message = self.add_message(room_id, author, body).await?;
+    let message_output = Self::message_output(&message);
+    self.send_room(room_id, Output::UserPosted(UserPostedOutput::new(message_output.clone())));
+
+    if self.clear_typing(author_id) {
+      self.send_typing(room_id, author_id, false).await;
+    }
+
+    self.msg_repo.add_new_message(room_id, message).await;
+    Ok(message_output)
+  }
+
+  /// Validates a new message and adds it to the feed; storing it is left to the caller.
+  async fn add_message(&self, room_id: &str, author: User, body: &str) -> Result<Message, OutputError> {
+    if !self.is_valid_body(body) {
+      return Err(OutputError::InvalidMessageBody);
+    }
+
+    let message = Message::new(author, room_id, body);
+    self.feed.write().await.add_message(message.clone());
+    Ok(message)
+  }
+
+  async fn process_edit(&self, room_id: &str, client_id: Uuid, input: EditInput) {
+    // verify that user exists
+    if !self.users.read().await.contains_key(&client_id) {
+      self.send_error(room_id, client_id, OutputError::UserNotJoined);
+      return;
+    }
+
     // validate message body
-    if input.body.is_empty() || input.body.len() > MAX_MESSAGE_BODY_LENGTH {
+    if !self.is_valid_body(&input.body) {
       self.send_error(room_id, client_id, OutputError::InvalidMessageBody);
       return;
     }
 
-    // Add new message to feed
-    if let Some(to_user) = self.load_room_user_except(room_id, client_id).await {
-      let message = Message::new(
-        user.clone(), User::new(to_user.user_id, to_user.username.as_str()), room_id, &input.body);
-      self.feed.write().await.add_message(message.clone());
+    let mut message = match self.find_message(room_id, input.message_id).await {
+      Some(message) => message,
+      None => {
+        self.send_error(room_id, client_id, OutputError::MessageNotExists);
+        return;
+      }
+    };
+
+    // only the author may edit a message
+    if message.from.id != client_id {
+      self.send_error(room_id, client_id, OutputError::NotMessageAuthor);
+      return;
+    }
+
+    // serve message, then update feed
+    let edited_at = Utc::now();
+    if self.msg_repo.update_message_body(room_id, message.id, &input.body, edited_at).await.is_none() {
+      error!("editing message {} of room {} failed", message.id, room_id);
+      self.send_error(room_id, client_id, OutputError::EditMessageFailed);
+      return;
+    }
+    message.body = input.body.clone();
+    message.edited_at = Some(edited_at);
+    self.feed.write().await.replace_message(message.clone());
+
+    // notify everyone about the edit
+    self.send_room(room_id, Output::MessageEdited(
+      MessageEditedOutput::new(String::from(room_id), Self::message_output(&message))));
+  }
+
+  async fn process_delete(&self, room_id: &str, client_id: Uuid, input: DeleteMessageInput) {
+    // verify that user exists
+    if !self.users.read().await.contains_key(&client_id) {
+      self.send_error(room_id, client_id, OutputError::UserNotJoined);
+      return;
+    }
+
+    let message = match self.find_message(room_id, input.message_id).await {
+      Some(message) => message,
+      None => {
+        self.send_error(room_id, client_id, OutputError::MessageNotExists);
+        return;
+      }
+    };
+
+    // the author and the room host may retract a message
+    if message.from.id != client_id {
+      let host_id = self.user_repo.load_host_room(room_id).await.map(|host| host.id);
+      let is_host = host_id == Some(client_id);
+      if !is_host {
+        self.send_error(room_id, client_id, OutputError::DeleteMessageNotAllowed);
+        return;
+      }
+    }
+
+    // remove from database, then from feed
+    if let Err(error) = self.msg_repo.delete_message(room_id, message.id).await {
+      error!("deleting message {} of room {} failed: {}", message.id, room_id, error);
+      self.send_error(room_id, client_id, OutputError::DeleteMessageFailed);
+      return;
+    }
+    self.feed.write().await.remove_message(message.id);
+
+    // notify everyone about the deletion
+    self.send_room(room_id, Output::MessageDeleted(
+      MessageDeletedOutput::new(String::from(room_id), message.id)));
+  }
+
+  /// Relays typing indicators to the other members, never more than one start per `typing_rate`.
+  async fn process_typing(&self, room_id: &str, client_id: Uuid, typing: bool, now: Instant) {
+    if !self.users.read().await.contains_key(&client_id) {
+      self.send_error(room_id, client_id, OutputError::UserNotJoined);
+      return;
+    }
+
+    let relay = if typing {
+      let mut state = self.typing.lock().unwrap();
+      let was_typing = state.expires.insert(client_id, now + self.options.typing_timeout).is_some();
+      let recently_relayed = matches!(state.relayed.get(&client_id),
+                                      Some(relayed) if now.duration_since(*relayed) < self.options.typing_rate);
+      if was_typing {
+        false
+      } else if recently_relayed {
+        // too soon after the previous one: dropped altogether
+        state.expires.remove(&client_id);
+        false
+      } else {
+        state.relayed.insert(client_id, now);
+        true
+      }
+    } else {
+      self.clear_typing(client_id)
+    };
+
+    if relay {
+      self.send_typing(room_id, client_id, typing).await;
+    }
+  }
+
+  /// Stops the indicators that outlived `typing_timeout`.
+  pub async fn expire_typing(&self, room_id: &str, now: Instant) {
+    let expired: Vec<Uuid> = {
+      let mut state = self.typing.lock().unwrap();
+      let expired = state.expires.iter()
+        .filter(|(_, expires)| **expires <= now)
+        .map(|(client_id, _)| *client_id)
+        .collect::<Vec<Uuid>>();
+      for client_id in expired.iter() {
+        state.expires.remove(client_id);
+      }
+      let typing_rate = self.options.typing_rate;
+      state.relayed.retain(|_, relayed| now.duration_since(*relayed) < typing_rate);
+      expired
+    };
+
+    for client_id in expired {
+      self.send_typing(room_id, client_id, false).await;
+    }
+  }
+
+  /// Forgets the user's indicator, telling whether one was shown.
+  fn clear_typing(&self, client_id: Uuid) -> bool {
+    self.typing.lock().unwrap().expires.remove(&client_id).is_some()
+  }
+
+  async fn send_typing(&self, room_id: &str, client_id: Uuid, typing: bool) {
+    self.send_ignored(room_id, client_id, Output::UserTyping(
+      UserTypingOutput::new(String::from(room_id), client_id, typing))).await;
+  }
+
+  async fn process_mark_read(&self, room_id: &str, client_id: Uuid, input: MarkReadInput) {
+    if !self.users.read().await.contains_key(&client_id) {
+      self.send_error(room_id, client_id, OutputError::UserNotJoined);
+      return;
+    }
+
+    if self.find_message(room_id, input.message_id).await.is_none() {
+      self.send_error(room_id, client_id, OutputError::MessageNotExists);
+      return;
+    }
+
+    // markers only move forward; reading an older message again changes nothing
+    match self.room_user_repo.advance_last_read(String::from(room_id), client_id, input.message_id).await {
+      Some(true) => {},
+      Some(false) => return,
+      None => {
+        self.send_error(room_id, client_id, OutputError::NotRoomMember);
+        return;
+      }
+    }
+
+    self.send_room(room_id, Output::ReadReceipt(
+      ReadReceiptOutput::new(String::from(room_id), client_id, input.message_id)));
+  }
+
+  async fn process_history(&self, room_id: &str, client_id: Uuid, input: LoadHistoryInput) {
+    if !self.is_member(room_id, client_id).await {
+      self.send_error(room_id, client_id, OutputError::NotRoomMember);
+      return;
+    }
+
+    match self.load_history(room_id, input.before.as_deref(), input.limit).await {
+      Ok(history) => self.send_targeted(room_id, client_id, Output::HistoryLoaded(history)),
+      Err(error) => self.send_error(room_id, client_id, error),
+    }
+  }
+
+  /// A page of messages older than the `before` cursor, newest first.
+  pub async fn load_history(&self, room_id: &str, before: Option<&str>, limit: Option<i32>) -> Result<HistoryLoadedOutput, OutputError> {
+    let before = match before.map(Self::decode_cursor) {
+      Some(None) => return Err(OutputError::InvalidCursor),
+      Some(before) => before,
+      None => None,
+    };
+    let limit = limit.unwrap_or(self.options.page_size).clamp(1, MAX_HISTORY_LIMIT);
+
+    // one more than asked tells whether an older page exists
+    let mut messages = self.msg_repo
+      .load_messages_by_room(room_id, before, limit + 1).await
+      .unwrap_or_default();
+    let next_cursor = if messages.len() > limit as usize {
+      messages.truncate(limit as usize);
+      messages.last().map(|message| Self::encode_cursor(message.id))
+    } else {
+      None
+    };
+
+    let messages = messages.iter().map(Self::message_output).collect();
+    Ok(HistoryLoadedOutput::new(String::from(room_id), messages, next_cursor))
+  }
+
+  /// History cursors are the id of the oldest message already delivered; clients only pass them back.
+  fn encode_cursor(message_id: Uuid) -> String {
+    message_id.to_simple().to_string()
+  }
+
+  fn decode_cursor(cursor: &str) -> Option<Uuid> {
+    Uuid::parse_str(cursor).ok().filter(|id| id.get_version_num() == 1)
+  }
+
+  /// Whether the user is one of the room's stored members, joined to the hub or not.
+  async fn is_member(&self, room_id: &str, user_id: Uuid) -> bool {
+    self.room_user_repo.load_one(String::from(room_id), user_id).await.is_some()
+  }
+
+  /// Looks the message up in the feed first, then in the database.
+  async fn find_message(&self, room_id: &str, message_id: Uuid) -> Option<Message> {
+    let cached = self.feed.read().await.find_message(message_id).cloned();
+    let message = match cached {
+      Some(message) => Some(message),
+      None => self.msg_repo.load_one_message(room_id, message_id).await,
+    };
+    message.filter(|message| message.room_id == room_id)
+  }
+
+  fn is_valid_body(&self, body: &str) -> bool {
+    !body.is_empty() && body.len() <= self.options.max_message_body_length
+  }
+
+  fn message_output(message: &Message) -> MessageOutput {
+    MessageOutput {
+      edited_at: message.edited_at,
+      ..MessageOutput::new(
+        message.id,
+        UserOutput::new(message.from.id, &message.from.name),
+        &message.body,
+        message.created_at,
+      )
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::sync::mpsc::Receiver;
+  use crate::domain::repository::Repositories;
+  use crate::model::room::Room;
+  use crate::model::room_user::RoomUser;
+
+  macro_rules! aw {
+    ($e:expr) => {
+      tokio_test::block_on($e)
+    };
+  }
+
+  const ROOM_ID: &str = "room";
+
+  struct Client {
+    id: Uuid,
+    receiver: Receiver<OutputParcel>,
+  }
+
+  impl Client {
+    fn outputs(&mut self) -> Vec<Output> {
+      let mut outputs = vec!();
+      while let Ok(parcel) = self.receiver.try_recv() {
+        outputs.push(parcel.output);
+      }
+      outputs
+    }
+  }
+
+  fn new_hub(repositories: &Repositories) -> (Hub, Arc<SessionRegistry>) {
+    let sessions = Arc::new(SessionRegistry::new(16));
+    let hub = Hub::new(Arc::clone(&sessions), Default::default(), repositories.user(), repositories.room_user(),
+                       repositories.message(), HubOptions::default());
+    (hub, sessions)
+  }
+
+  fn join(hub: &Hub, sessions: &SessionRegistry, name: &str) -> Client {
+    let id = Uuid::new_v4();
+    let (_, receiver) = sessions.register(ROOM_ID, id);
+    aw!(hub.process(InputParcel::new(id, ROOM_ID.to_string(),
+                                     Input::JoinRoom(JoinInput { client_id: id, name: name.to_string() }))));
+    Client { id, receiver }
+  }
+
+  fn post(hub: &Hub, client: &mut Client, body: &str) -> MessageOutput {
+    aw!(hub.process(InputParcel::new(client.id, ROOM_ID.to_string(),
+                                     Input::PostMessage(PostInput { client_id: client.id, body: body.to_string() }))));
+    client.outputs().into_iter()
+      .find_map(|output| match output {
+        Output::Posted(posted) => Some(posted.message),
+        _ => None,
+      })
+      .unwrap()
+  }
 
-      // report send message success
-      let message_output = MessageOutput::new(
-        message.id, UserOutput::new(user.id, &user.name), &message.body
-      );
+  fn edit(hub: &Hub, client: &Client, message_id: Uuid, body: &str) {
+    aw!(hub.process(InputParcel::new(client.id, ROOM_ID.to_string(), Input::EditMessage(
+      EditInput { client_id: client.id, message_id, body: body.to_string() }))));
+  }
 
-      // report post status
-      self.send_targeted(room_id, client_id, Output::Posted(PostedOutput::new(message_output.clone())));
+  fn delete(hub: &Hub, client: &Client, message_id: Uuid) {
+    aw!(hub.process(InputParcel::new(client.id, ROOM_ID.to_string(), Input::DeleteMessage(
+      DeleteMessageInput { client_id: client.id, message_id }))));
+  }
 
-      // notify everyone about new message
-      // self.send_ignored(room_id, client_id, Output::UserPosted(UserPostedOutput::new(message_output))).await;
+  #[test]
+  fn test001_author_edits_message() {
+    let (hub, sessions) = new_hub(&Repositories::in_memory());
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    let message = post(&hub, &mut alice, "hello");
+    bob.outputs();
+
+    edit(&hub, &alice, message.id, "hello there");
+
+    for outputs in [alice.outputs(), bob.outputs()].iter() {
+      match outputs.as_slice() {
+        [Output::MessageEdited(edited)] => {
+          assert_eq!(edited.message.id, message.id);
+          assert_eq!(edited.message.body, "hello there");
+          assert!(edited.message.edited_at.is_some());
+        },
+        other => panic!("unexpected outputs {:?}", other),
+      }
+    }
+    assert_eq!(aw!(hub.msg_repo.load_one_message(ROOM_ID, message.id)).unwrap().body, "hello there");
+  }
+
+  #[test]
+  fn test002_only_author_edits_valid_body() {
+    let (hub, sessions) = new_hub(&Repositories::in_memory());
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    let message = post(&hub, &mut alice, "hello");
+    bob.outputs();
+
+    edit(&hub, &bob, message.id, "hijacked");
+    assert_eq!(bob.outputs(), vec![Output::Error(OutputError::NotMessageAuthor)]);
+
+    edit(&hub, &alice, message.id, "");
+    assert_eq!(alice.outputs(), vec![Output::Error(OutputError::InvalidMessageBody)]);
+
+    edit(&hub, &alice, Uuid::new_v4(), "hello");
+    assert_eq!(alice.outputs(), vec![Output::Error(OutputError::MessageNotExists)]);
+  }
+
+  #[test]
+  fn test003_author_and_host_delete_messages() {
+    let repositories = Repositories::in_memory();
+    let (hub, sessions) = new_hub(&repositories);
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    let mut carol = join(&hub, &sessions, "carol");
+    aw!(repositories.room().create_room(Room::new(ROOM_ID.to_string(), "title".to_string(), alice.id,
+                                                  "alice".to_string(), None, Utc::now(), "key".to_string())));
+
+    let first = post(&hub, &mut bob, "first");
+    let second = post(&hub, &mut bob, "second");
+    alice.outputs();
+    carol.outputs();
+
+    // carol is neither the author nor the host
+    delete(&hub, &carol, first.id);
+    assert_eq!(carol.outputs(), vec![Output::Error(OutputError::DeleteMessageNotAllowed)]);
+
+    delete(&hub, &bob, first.id);
+    let deleted = Output::MessageDeleted(MessageDeletedOutput::new(ROOM_ID.to_string(), first.id));
+    assert_eq!(carol.outputs(), vec![deleted.clone()]);
+    assert_eq!(bob.outputs(), vec![deleted]);
+    assert!(!aw!(hub.msg_repo.check_message_exists(ROOM_ID, first.id)));
+    assert!(aw!(hub.feed.read()).find_message(first.id).is_none());
+
+    delete(&hub, &bob, first.id);
+    assert_eq!(bob.outputs(), vec![Output::Error(OutputError::MessageNotExists)]);
+
+    // alice hosts the room
+    alice.outputs();
+    delete(&hub, &alice, second.id);
+    assert_eq!(alice.outputs(), vec![Output::MessageDeleted(MessageDeletedOutput::new(ROOM_ID.to_string(), second.id))]);
+  }
+
+  #[test]
+  fn test004_posts_reach_every_other_member() {
+    let (hub, sessions) = new_hub(&Repositories::in_memory());
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    let (_, mut stranger) = sessions.register("other-room", bob.id);
+    alice.outputs();
+    bob.outputs();
+
+    let message = post(&hub, &mut alice, "hello");
+
+    assert_eq!(bob.outputs(), vec![Output::UserPosted(UserPostedOutput::new(message.clone()))]);
+    assert!(stranger.try_recv().is_err());
+
+    let reply = post(&hub, &mut bob, "hi alice");
+    assert_eq!(alice.outputs(), vec![Output::UserPosted(UserPostedOutput::new(reply))]);
+  }
 
-      // serve message
-      self.msg_repo.add_new_message(room_id, message).await;
+  #[test]
+  fn test005_room_history_keeps_every_member() {
+    let repositories = Repositories::in_memory();
+    let (hub, sessions) = new_hub(&repositories);
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    let mut carol = join(&hub, &sessions, "carol");
+    aw!(repositories.room().create_room(Room::new(ROOM_ID.to_string(), "title".to_string(), alice.id,
+                                                  "alice".to_string(), None, Utc::now(), "key".to_string())));
+
+    post(&hub, &mut alice, "one");
+    post(&hub, &mut bob, "two");
+    post(&hub, &mut carol, "three");
+
+    // a fresh hub reloads the room from storage
+    let (hub, sessions) = new_hub(&repositories);
+    let (_, mut watcher) = sessions.register(ROOM_ID, Uuid::nil());
+    aw!(hub.process(InputParcel::new(alice.id, ROOM_ID.to_string(), Input::LoadRoom(LoadRoomInput { from_id: alice.id }))));
+
+    match watcher.try_recv().unwrap().output {
+      Output::RoomLoaded(loaded) => {
+        let mut bodies: Vec<String> = loaded.recent_messages.into_iter().map(|message| message.body).collect();
+        bodies.sort();
+        assert_eq!(bodies, vec!["one", "three", "two"]);
+      },
+      other => panic!("unexpected output {:?}", other),
     }
   }
-}
\ No newline at end of file
+
+  fn add_member(repositories: &Repositories, client: &Client) {
+    aw!(repositories.room_user().create_room_users(RoomUser::new(
+      ROOM_ID.to_string(), "title".to_string(), client.id, "member".to_string(), Utc::now())));
+  }
+
+  fn history(hub: &Hub, client: &mut Client, before: Option<String>, limit: i32) -> Output {
+    aw!(hub.process(InputParcel::new(client.id, ROOM_ID.to_string(), Input::LoadHistory(
+      LoadHistoryInput { client_id: client.id, before, limit: Some(limit) }))));
+    client.outputs().pop().unwrap()
+  }
+
+  #[test]
+  fn test006_history_pages_with_cursor() {
+    let repositories = Repositories::in_memory();
+    let (hub, sessions) = new_hub(&repositories);
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut stranger = join(&hub, &sessions, "stranger");
+    add_member(&repositories, &alice);
+    for body in &["one", "two", "three", "four", "five"] {
+      post(&hub, &mut alice, body);
+    }
+
+    let mut cursor = None;
+    let mut pages = vec!();
+    loop {
+      match history(&hub, &mut alice, cursor, 2) {
+        Output::HistoryLoaded(page) => {
+          pages.push(page.messages.into_iter().map(|message| message.body).collect::<Vec<String>>());
+          cursor = page.next_cursor;
+        },
+        other => panic!("unexpected output {:?}", other),
+      }
+      if cursor.is_none() {
+        break;
+      }
+    }
+    assert_eq!(pages, vec![vec!["five", "four"], vec!["three", "two"], vec!["one"]]);
+
+    assert_eq!(history(&hub, &mut alice, Some("garbage".to_string()), 2), Output::Error(OutputError::InvalidCursor));
+    assert_eq!(history(&hub, &mut stranger, None, 2), Output::Error(OutputError::NotRoomMember));
+  }
+
+  #[test]
+  fn test007_presence_changes_reach_members_only() {
+    let (hub, sessions) = new_hub(&Repositories::in_memory());
+    let mut alice = join(&hub, &sessions, "alice");
+    let stranger = Uuid::new_v4();
+    alice.outputs();
+
+    let away = PresenceOutput::new(alice.id, PresenceStatus::Away, Some("lunch".to_string()));
+    aw!(hub.on_presence_changed(ROOM_ID, away.clone()));
+    aw!(hub.on_presence_changed(ROOM_ID, PresenceOutput::new(stranger, PresenceStatus::Online, None)));
+
+    assert_eq!(alice.outputs(), vec![Output::PresenceChanged(away)]);
+  }
+
+  fn typing(hub: &Hub, client: &Client, typing: bool, now: Instant) {
+    aw!(hub.process_typing(ROOM_ID, client.id, typing, now));
+  }
+
+  #[test]
+  fn test008_typing_is_relayed_expired_and_rate_limited() {
+    let (hub, sessions) = new_hub(&Repositories::in_memory());
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    alice.outputs();
+    bob.outputs();
+    let started = Output::UserTyping(UserTypingOutput::new(ROOM_ID.to_string(), alice.id, true));
+    let stopped = Output::UserTyping(UserTypingOutput::new(ROOM_ID.to_string(), alice.id, false));
+    let now = Instant::now();
+
+    typing(&hub, &alice, true, now);
+    typing(&hub, &alice, true, now + Duration::from_secs(1));
+    typing(&hub, &alice, false, now + Duration::from_secs(1));
+    assert_eq!(bob.outputs(), vec![started.clone(), stopped.clone()]);
+    assert!(alice.outputs().is_empty());
+
+    // restarting right away is dropped
+    typing(&hub, &alice, true, now + Duration::from_secs(1));
+    typing(&hub, &alice, false, now + Duration::from_secs(1));
+    assert!(bob.outputs().is_empty());
+
+    // without a stop the indicator expires
+    let later = now + Duration::from_secs(10);
+    typing(&hub, &alice, true, later);
+    aw!(hub.expire_typing(ROOM_ID, later + Duration::from_secs(1)));
+    assert_eq!(bob.outputs(), vec![started.clone()]);
+    aw!(hub.expire_typing(ROOM_ID, later + HubOptions::default().typing_timeout));
+    assert_eq!(bob.outputs(), vec![stopped.clone()]);
+
+    // typing on does not hold back the next start once the last relayed one is old enough
+    let later = later + Duration::from_secs(10);
+    for seconds in &[0, 1, 3, 4] {
+      typing(&hub, &alice, true, later + Duration::from_secs(*seconds));
+    }
+    typing(&hub, &alice, false, later + Duration::from_secs(4));
+    typing(&hub, &alice, true, later + Duration::from_secs(4));
+    assert_eq!(bob.outputs(), vec![started.clone(), stopped, started]);
+  }
+
+  fn mark_read(hub: &Hub, client: &Client, message_id: Uuid) {
+    aw!(hub.process(InputParcel::new(client.id, ROOM_ID.to_string(), Input::MarkRead(
+      MarkReadInput { client_id: client.id, message_id }))));
+  }
+
+  #[test]
+  fn test009_read_markers_only_move_forward() {
+    let repositories = Repositories::in_memory();
+    let (hub, sessions) = new_hub(&repositories);
+    let mut alice = join(&hub, &sessions, "alice");
+    let mut bob = join(&hub, &sessions, "bob");
+    let mut carol = join(&hub, &sessions, "carol");
+    add_member(&repositories, &alice);
+    add_member(&repositories, &bob);
+    let first = post(&hub, &mut alice, "first");
+    let second = post(&hub, &mut alice, "second");
+    bob.outputs();
+    carol.outputs();
+
+    mark_read(&hub, &bob, second.id);
+    let receipt = Output::ReadReceipt(ReadReceiptOutput::new(ROOM_ID.to_string(), bob.id, second.id));
+    assert_eq!(alice.outputs(), vec![receipt.clone()]);
+    assert_eq!(bob.outputs(), vec![receipt.clone()]);
+    assert_eq!(carol.outputs(), vec![receipt]);
+
+    mark_read(&hub, &bob, first.id);
+    assert!(alice.outputs().is_empty());
+    let members = aw!(repositories.room_user().load_by_room(ROOM_ID.to_string())).unwrap();
+    let bob_member = members.iter().find(|member| member.user_id == bob.id).unwrap();
+    assert_eq!(bob_member.last_read, Some(second.id));
+
+    mark_read(&hub, &bob, Uuid::new_v4());
+    assert_eq!(bob.outputs(), vec![Output::Error(OutputError::MessageNotExists)]);
+    mark_read(&hub, &carol, second.id);
+    assert_eq!(carol.outputs(), vec![Output::Error(OutputError::NotRoomMember)]);
+  }
+}
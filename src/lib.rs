@@ -1,16 +1,23 @@
 #[macro_use(lazy_static)]
 extern crate lazy_static;
 
+pub mod auth;
+pub mod config;
+pub mod password;
+pub mod accounts;
+pub mod migration;
 pub mod model;
 pub mod proto;
 pub mod hub;
 pub mod server;
+pub mod rest;
+pub mod session;
+pub mod presence;
 pub mod client;
 pub mod error;
 pub mod room_storage;
 pub mod user_storage;
 
+#[cfg(feature = "cassandra")]
 pub mod cass;
 pub mod domain;
-
-pub mod utils;
\ No newline at end of file
@@ -0,0 +1,186 @@
+use std::sync::Arc;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+use chat_server::auth::JwtVerifier;
+use chat_server::config::{Config, ConfigArgs};
+use chat_server::domain::repository::Repositories;
+use chat_server::error::{Error, Result};
+use chat_server::server::Server;
+
+#[derive(StructOpt)]
+#[structopt(name = "chat-server", about = "Websocket chat server")]
+struct Args {
+  #[structopt(flatten)]
+  config: ConfigArgs,
+
+  #[structopt(subcommand)]
+  command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+  /// Serve the websocket routes; both sets are mounted when neither flag is given
+  Serve {
+    /// Mount /ws/{user_id}/rooms and GET /api/rooms
+    #[structopt(long)]
+    rooms: bool,
+
+    /// Mount /ws/{room_id}/feeds and /api/rooms/{room_id}
+    #[structopt(long)]
+    feeds: bool,
+  },
+  /// Manage the Cassandra schema
+  Migrate(MigrateCommand),
+  /// Inspect and clean up stored data
+  Admin(AdminCommand),
+}
+
+#[derive(StructOpt)]
+#[cfg_attr(not(feature = "cassandra"), allow(dead_code))]
+enum MigrateCommand {
+  /// Apply every pending migration
+  Apply {
+    /// Print the pending migrations without running them
+    #[structopt(long)]
+    dry_run: bool,
+  },
+  /// List the migrations and whether they have been applied
+  Status,
+  /// Copy the messages of the retired `message` table into `room_message`; `apply` does it
+  /// with migration 0007, this is for clusters that applied 0007 before it did
+  BackfillMessages,
+}
+
+#[derive(StructOpt)]
+enum AdminCommand {
+  /// List every room
+  Rooms,
+  /// List users, newest first
+  Users {
+    #[structopt(long, default_value = "1")]
+    page: i32,
+
+    #[structopt(long, default_value = "20")]
+    size: i32,
+  },
+  /// Delete a room and its memberships
+  DeleteRoom { room_id: String },
+  /// Delete a user
+  DeleteUser { user_id: Uuid },
+}
+
+#[tokio::main]
+async fn main() {
+  env_logger::init();
+
+  let args = Args::from_args();
+  let config = match Config::load(&args.config) {
+    Ok(config) => config,
+    Err(error) => {
+      eprintln!("{}", error);
+      std::process::exit(2);
+    }
+  };
+
+  let result = match args.command {
+    Command::Serve { rooms, feeds } => serve(&config, rooms, feeds).await,
+    Command::Migrate(command) => migrate(&config, command).await,
+    Command::Admin(command) => admin(&config, command).await,
+  };
+
+  if let Err(error) = result {
+    eprintln!("{}", error);
+    std::process::exit(1);
+  }
+}
+
+async fn serve(config: &Config, rooms: bool, feeds: bool) -> Result<()> {
+  let secret = config.auth.secret.as_deref()
+    .ok_or_else(|| Error::Config("auth.secret is required to serve".to_string()))?;
+  let tokens = Arc::new(JwtVerifier::new(secret));
+  let repositories = Repositories::connect(config).await?;
+
+  let (rooms, feeds) = if rooms || feeds { (rooms, feeds) } else { (true, true) };
+  Server::new(config, repositories, tokens.clone(), tokens, rooms, feeds).run().await;
+  Ok(())
+}
+
+#[cfg(feature = "cassandra")]
+async fn migrate(config: &Config, command: MigrateCommand) -> Result<()> {
+  use chat_server::cass::server_node::ServerNode;
+  use chat_server::migration::MigrationState;
+
+  let mut node = ServerNode::with_config(config.cassandra.clone());
+  let migrator = node.migrator().await?;
+
+  match command {
+    MigrateCommand::Status => {
+      for item in migrator.status().await? {
+        let state = match item.state {
+          MigrationState::Pending => "pending".to_string(),
+          MigrationState::Modified => "MODIFIED".to_string(),
+          MigrationState::Applied => format!("applied {}", item.applied_at.unwrap()),
+        };
+        println!("{:04} {:<24} {}", item.migration.version, item.migration.name, state);
+      }
+    },
+    MigrateCommand::Apply { dry_run: true } => {
+      for migration in migrator.dry_run().await? {
+        println!("-- {:04} {}", migration.version, migration.name);
+        for statement in migration.statements(&config.cassandra.keyspace) {
+          println!("{};", statement);
+        }
+      }
+    },
+    MigrateCommand::Apply { dry_run: false } => {
+      let applied = migrator.apply().await?;
+      for migration in applied.iter() {
+        println!("applied migration {:04} {}", migration.version, migration.name);
+      }
+      if applied.is_empty() {
+        println!("schema is up to date");
+      }
+    },
+    MigrateCommand::BackfillMessages => {
+      let copied = migrator.backfill_messages().await?;
+      println!("copied {} messages to room_message", copied);
+    },
+  }
+  Ok(())
+}
+
+#[cfg(not(feature = "cassandra"))]
+async fn migrate(_config: &Config, _command: MigrateCommand) -> Result<()> {
+  Err(Error::System("migrations need the cassandra feature".to_string()))
+}
+
+async fn admin(config: &Config, command: AdminCommand) -> Result<()> {
+  let repositories = Repositories::connect(config).await?;
+
+  match command {
+    AdminCommand::Rooms => {
+      for room in repositories.room().load_rooms().await {
+        println!("{} {:<24} host {} ({})", room.room_id, room.room_title, room.host_info.name, room.host_info.id);
+      }
+    },
+    AdminCommand::Users { page, size } => {
+      for user in repositories.user().load_users(page, size).await.unwrap_or_default() {
+        println!("{} {}", user.id, user.name);
+      }
+    },
+    AdminCommand::DeleteRoom { room_id } => {
+      if !repositories.room().room_exists(room_id.as_str()).await {
+        return Err(Error::System(format!("room {} does not exist", room_id)));
+      }
+      repositories.room_user().delete_by_room(room_id.clone()).await?;
+      repositories.room().delete_room(room_id.as_str()).await?;
+      println!("deleted room {}", room_id);
+    },
+    AdminCommand::DeleteUser { user_id } => {
+      repositories.user().delete_user(user_id).await?;
+      println!("deleted user {}", user_id);
+    },
+  }
+  Ok(())
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+
+pub const KEYSPACE_PLACEHOLDER: &str = "{keyspace}";
+
+pub const KEYSPACE_SOURCE: &str = include_str!("../cql/keyspace.cql");
+
+pub const SCHEMA_VERSION_SOURCE: &str = include_str!("../cql/schema_version.cql");
+
+/// Every schema change, in the order it is applied. Append new entries with the next
+/// version; never edit or reorder one that has already shipped.
+pub const MIGRATIONS: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "create_room",
+    source: include_str!("../cql/migrations/0001_create_room.cql"),
+  },
+  Migration {
+    version: 2,
+    name: "create_user",
+    source: include_str!("../cql/migrations/0002_create_user.cql"),
+  },
+  Migration {
+    version: 3,
+    name: "create_room_users",
+    source: include_str!("../cql/migrations/0003_create_room_users.cql"),
+  },
+  Migration {
+    version: 4,
+    name: "create_message",
+    source: include_str!("../cql/migrations/0004_create_message.cql"),
+  },
+  Migration {
+    version: 5,
+    name: "add_message_created_at",
+    source: include_str!("../cql/migrations/0005_add_message_created_at.cql"),
+  },
+  Migration {
+    version: 6,
+    name: "add_message_edited_at",
+    source: include_str!("../cql/migrations/0006_add_message_edited_at.cql"),
+  },
+  // retires `message`: messages are read from `room_message` only, and the migrator copies
+  // the old rows over when applying it
+  Migration {
+    version: 7,
+    name: "create_room_message",
+    source: include_str!("../cql/migrations/0007_create_room_message.cql"),
+  },
+  Migration {
+    version: 8,
+    name: "add_room_users_last_read",
+    source: include_str!("../cql/migrations/0008_add_room_users_last_read.cql"),
+  },
+  Migration {
+    version: 9,
+    name: "create_account",
+    source: include_str!("../cql/migrations/0009_create_account.cql"),
+  },
+];
+
+#[derive(Debug, PartialEq)]
+pub struct Migration {
+  pub version: i32,
+  pub name: &'static str,
+  pub source: &'static str,
+}
+
+/// A row of the `schema_version` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedMigration {
+  pub version: i32,
+  pub name: String,
+  pub checksum: String,
+  pub applied_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MigrationState {
+  Pending,
+  Applied,
+  /// Applied, but the cql has been edited since.
+  Modified,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MigrationStatus {
+  pub migration: &'static Migration,
+  pub state: MigrationState,
+  pub applied_at: Option<DateTime<Utc>>,
+}
+
+impl Migration {
+
+  /// Statements of the migration for the given keyspace, split on `;`.
+  pub fn statements(&self, keyspace: &str) -> Vec<String> {
+    split_statements(self.source, keyspace)
+  }
+
+  /// Statements left to run when the migration may have died part way through: an `ADD`
+  /// of a column that `columns`, the `(table, column)` pairs of the keyspace, already holds
+  /// is skipped, as Cassandra has no `IF NOT EXISTS` for it.
+  pub fn remaining_statements(&self, keyspace: &str, columns: &HashSet<(String, String)>) -> Vec<String> {
+    self.statements(keyspace)
+      .into_iter()
+      .filter(|statement| match added_column(statement) {
+        Some(column) => !columns.contains(&column),
+        None => true,
+      })
+      .collect()
+  }
+
+  /// FNV-1a hash of the source, used to detect migrations edited after being applied.
+  pub fn checksum(&self) -> String {
+    let hash = self.source.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+      (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+  }
+}
+
+pub fn split_statements(source: &str, keyspace: &str) -> Vec<String> {
+  source.replace(KEYSPACE_PLACEHOLDER, keyspace)
+    .split(';')
+    .map(|statement| statement.trim())
+    .filter(|statement| !statement.is_empty())
+    .map(String::from)
+    .collect()
+}
+
+/// `(table, column)` added by an `ALTER TABLE ... ADD` statement, lowercased like the unquoted
+/// names Cassandra stores.
+pub fn added_column(statement: &str) -> Option<(String, String)> {
+  let words: Vec<&str> = statement.split_whitespace().collect();
+  match words.as_slice() {
+    [alter, table, name, add, column, ..] if alter.eq_ignore_ascii_case("alter")
+      && table.eq_ignore_ascii_case("table") && add.eq_ignore_ascii_case("add") => {
+      let table = name.rsplit('.').next().unwrap_or(name);
+      Some((table.to_lowercase(), column.to_lowercase()))
+    },
+    _ => None,
+  }
+}
+
+/// Matches the known migrations against the rows read from `schema_version`.
+pub fn plan(applied: &[AppliedMigration]) -> Vec<MigrationStatus> {
+  let applied: HashMap<i32, &AppliedMigration> = applied.iter()
+    .map(|migration| (migration.version, migration))
+    .collect();
+
+  MIGRATIONS.iter()
+    .map(|migration| match applied.get(&migration.version) {
+      None => MigrationStatus { migration, state: MigrationState::Pending, applied_at: None },
+      Some(row) => MigrationStatus {
+        migration,
+        state: if row.checksum == migration.checksum() { MigrationState::Applied } else { MigrationState::Modified },
+        applied_at: Some(row.applied_at),
+      },
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn applied(migration: &Migration, checksum: String) -> AppliedMigration {
+    AppliedMigration {
+      version: migration.version,
+      name: migration.name.to_string(),
+      checksum,
+      applied_at: Utc::now(),
+    }
+  }
+
+  #[test]
+  fn test001_versions_are_strictly_increasing() {
+    let versions: Vec<i32> = MIGRATIONS.iter().map(|migration| migration.version).collect();
+    assert_eq!(versions, (1..=MIGRATIONS.len() as i32).collect::<Vec<i32>>());
+  }
+
+  #[test]
+  fn test002_statements_use_keyspace() {
+    let statements = split_statements("CREATE TABLE {keyspace}.a (id int PRIMARY KEY);\n\nDROP TABLE {keyspace}.b;\n", "chat_test");
+    assert_eq!(statements, vec!["CREATE TABLE chat_test.a (id int PRIMARY KEY)", "DROP TABLE chat_test.b"]);
+
+    for migration in MIGRATIONS {
+      assert!(!migration.statements("chat_app").is_empty());
+    }
+  }
+
+  #[test]
+  fn test003_plan_marks_pending_applied_and_modified() {
+    let rows = vec![
+      applied(&MIGRATIONS[0], MIGRATIONS[0].checksum()),
+      applied(&MIGRATIONS[1], "0000000000000000".to_string()),
+    ];
+
+    let states: Vec<MigrationState> = plan(&rows).iter().map(|status| status.state).collect();
+    assert_eq!(states[0], MigrationState::Applied);
+    assert_eq!(states[1], MigrationState::Modified);
+    assert!(states[2..].iter().all(|state| *state == MigrationState::Pending));
+  }
+
+  #[test]
+  fn test004_partly_applied_migrations_skip_added_columns() {
+    let migration = Migration {
+      version: 10,
+      name: "add_message_columns",
+      source: "ALTER TABLE {keyspace}.message ADD created_at timestamp;\nALTER TABLE {keyspace}.message ADD edited_at timestamp;\n",
+    };
+    assert_eq!(added_column("ALTER TABLE chat_app.Message ADD Created_At timestamp"),
+               Some(("message".to_string(), "created_at".to_string())));
+    assert_eq!(added_column("CREATE TABLE IF NOT EXISTS chat_app.message (id timeuuid PRIMARY KEY)"), None);
+
+    let mut columns = HashSet::new();
+    assert_eq!(migration.remaining_statements("chat_app", &columns), migration.statements("chat_app"));
+
+    // died after the first statement, before being recorded
+    columns.insert(("message".to_string(), "created_at".to_string()));
+    assert_eq!(migration.remaining_statements("chat_app", &columns),
+               vec!["ALTER TABLE chat_app.message ADD edited_at timestamp"]);
+
+    columns.insert(("message".to_string(), "edited_at".to_string()));
+    assert!(migration.remaining_statements("chat_app", &columns).is_empty());
+  }
+}
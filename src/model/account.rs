@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// A registered user. The id is the one tokens name and rooms know the user by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+  pub id: Uuid,
+  /// Unique, lowercase; what the user logs in with.
+  pub username: String,
+  /// Salted hash, see `crate::password`.
+  pub password_hash: String,
+  pub display_name: String,
+  pub avatar_url: Option<String>,
+  pub bio: Option<String>,
+  pub create_at: DateTime<Utc>,
+}
+
+impl Account {
+  pub fn new(username: &str, password_hash: String, display_name: &str) -> Self {
+    Account {
+      id: Uuid::new_v4(),
+      username: String::from(username),
+      password_hash,
+      display_name: String::from(display_name),
+      avatar_url: None,
+      bio: None,
+      create_at: Utc::now(),
+    }
+  }
+}
@@ -1,22 +1,102 @@
+use std::collections::VecDeque;
+use uuid::Uuid;
+
 use crate::model::message::Message;
 
-#[derive(Default)]
+/// The most recent messages of a room, oldest first.
+///
+/// Holds at most `capacity` messages, each id once; adding past the capacity evicts the oldest
+/// one, which then only lives in storage.
 pub struct Feed {
-  messages: Vec<Message>,
-  size: usize,
+  messages: VecDeque<Message>,
+  capacity: usize,
 }
 
 impl Feed {
+  pub fn new(capacity: usize) -> Self {
+    Feed {
+      messages: VecDeque::with_capacity(capacity),
+      capacity,
+    }
+  }
+
+  /// Inserts a message at its place in time, replacing a message with the same id.
   pub fn add_message(&mut self, message: Message) {
-    self.messages.push(message);
-    self.size += 1;
+    if let Some(existing) = self.messages.iter_mut().find(|existing| existing.id == message.id) {
+      *existing = message;
+      return;
+    }
+
+    let order = Message::id_order(&message.id);
+    let index = self.messages.iter()
+      .rposition(|existing| Message::id_order(&existing.id) < order)
+      .map_or(0, |index| index + 1);
+
+    // older than everything kept in a full feed: storage already has it
+    if index == 0 && self.messages.len() >= self.capacity {
+      return;
+    }
+
+    self.messages.insert(index, message);
+    while self.messages.len() > self.capacity {
+      self.messages.pop_front();
+    }
   }
 
   pub fn messages_iter(&self) -> impl Iterator<Item = &Message> {
     self.messages.iter()
   }
 
+  pub fn find_message(&self, id: Uuid) -> Option<&Message> {
+    self.messages.iter().find(|message| message.id == id)
+  }
+
+  /// Swaps in the new version of a message already in the feed; unknown messages are ignored.
+  pub fn replace_message(&mut self, message: Message) {
+    if let Some(existing) = self.messages.iter_mut().find(|existing| existing.id == message.id) {
+      *existing = message;
+    }
+  }
+
+  pub fn remove_message(&mut self, id: Uuid) -> Option<Message> {
+    let index = self.messages.iter().position(|message| message.id == id)?;
+    self.messages.remove(index)
+  }
+
+  pub fn len(&self) -> usize {
+    self.messages.len()
+  }
+
   pub fn is_empty(&self) -> bool {
-    self.size == 0
+    self.messages.is_empty()
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::user::User;
+
+  #[test]
+  fn test001_keeps_newest_messages_in_order_once() {
+    let alice = User::new(Uuid::new_v4(), "alice");
+    let messages: Vec<Message> = (0..4)
+      .map(|index| Message::new(alice.clone(), "room", &index.to_string()))
+      .collect();
+
+    let mut feed = Feed::new(3);
+    // loaded newest first from storage, then a duplicate
+    for message in messages.iter().rev() {
+      feed.add_message(message.clone());
+    }
+    feed.add_message(messages[3].clone());
+
+    let bodies: Vec<&str> = feed.messages_iter().map(|message| message.body.as_str()).collect();
+    assert_eq!(bodies, vec!["1", "2", "3"]);
+
+    feed.add_message(Message::new(alice, "room", "4"));
+    let bodies: Vec<&str> = feed.messages_iter().map(|message| message.body.as_str()).collect();
+    assert_eq!(bodies, vec!["2", "3", "4"]);
+    assert_eq!(feed.len(), 3);
+  }
+}
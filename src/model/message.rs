@@ -1,25 +1,74 @@
 use crate::model::user::User;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
+use uuid::v1::{Context, Timestamp};
+
+lazy_static! {
+  static ref CLOCK_SEQUENCE: Context = Context::new(Uuid::new_v4().as_bytes()[0].into());
+
+  // random node id with the multicast bit set, as RFC 4122 asks for ids not taken from a MAC address
+  static ref NODE_ID: [u8; 6] = {
+    let mut node_id = [0u8; 6];
+    node_id.copy_from_slice(&Uuid::new_v4().as_bytes()[..6]);
+    node_id[0] |= 0x01;
+    node_id
+  };
+}
 
 #[derive(Debug, Clone)]
 pub struct Message {
   pub id: Uuid,
 
   pub from: User,
-  pub to: User,
 
   pub room_id: String,
   pub body: String,
+  pub created_at: DateTime<Utc>,
+  pub edited_at: Option<DateTime<Utc>>,
 }
 
 impl Message {
-  pub fn new(from: User, to: User, room_id: &str, body: &str) -> Self {
+  pub fn new(from: User, room_id: &str, body: &str) -> Self {
+    let created_at = Utc::now();
     Message {
-      id: Uuid::default(),
+      id: Self::new_id(created_at),
       from,
-      to,
       room_id: String::from(room_id),
       body: String::from(body),
+      created_at,
+      edited_at: None,
     }
   }
+
+  /// Time-based (version 1) id for a message created at `created_at`; it is what the
+  /// message table's TIMEUUID column expects and orders like the creation time.
+  pub fn new_id(created_at: DateTime<Utc>) -> Uuid {
+    let timestamp = Timestamp::from_unix(
+      &*CLOCK_SEQUENCE, created_at.timestamp() as u64, created_at.timestamp_subsec_nanos());
+    Uuid::new_v1(timestamp, &*NODE_ID).unwrap()
+  }
+
+  /// Sort key of a time-based id, in the order a TIMEUUID column keeps them.
+  pub fn id_order(id: &Uuid) -> (u64, u16) {
+    id.to_timestamp().map(|timestamp| timestamp.to_rfc4122()).unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test001_new_messages_get_distinct_time_based_ids() {
+    let from = User::new(Uuid::new_v4(), "alice");
+    let first = Message::new(from.clone(), "room", "first");
+    let second = Message::new(from, "room", "second");
+
+    assert_eq!(first.id.get_version_num(), 1);
+    assert_ne!(first.id, second.id);
+
+    let (seconds, nanos) = first.id.to_timestamp().unwrap().to_unix();
+    assert_eq!(seconds as i64, first.created_at.timestamp());
+    assert_eq!(nanos / 100, first.created_at.timestamp_subsec_nanos() / 100);
+  }
 }
\ No newline at end of file
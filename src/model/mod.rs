@@ -2,4 +2,5 @@ pub mod user;
 pub mod message;
 pub mod feed;
 pub mod room;
-pub mod room_user;
\ No newline at end of file
+pub mod room_user;
+pub mod account;
\ No newline at end of file
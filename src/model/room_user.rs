@@ -8,6 +8,8 @@ pub struct RoomUser {
     pub username: String,
     pub room_title: String,
     pub create_at: DateTime<Utc>,
+    /// Newest message of the room the member has read.
+    pub last_read: Option<Uuid>,
 }
 
 impl RoomUser {
@@ -24,6 +26,7 @@ impl RoomUser {
             user_id,
             username,
             create_at,
+            last_read: None,
         }
     }
 }
\ No newline at end of file
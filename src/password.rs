@@ -0,0 +1,74 @@
+use hmac::Hmac;
+use rand::RngCore;
+use sha2::Sha256;
+
+const SCHEME: &str = "pbkdf2-sha256";
+const ITERATIONS: u32 = 100_000;
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: usize = 32;
+
+/// Hashes a password with a fresh random salt, as `pbkdf2-sha256$<iterations>$<salt>$<hash>`.
+///
+/// The iteration count is stored along so it can be raised without invalidating older hashes.
+pub fn hash(password: &str) -> String {
+  let mut salt = [0u8; SALT_LENGTH];
+  rand::thread_rng().fill_bytes(&mut salt);
+  encode(ITERATIONS, &salt, &derive(password, &salt, ITERATIONS))
+}
+
+/// Checks a password against a hash made by `hash`; unreadable hashes match nothing.
+pub fn verify(password: &str, encoded: &str) -> bool {
+  let parts: Vec<&str> = encoded.split('$').collect();
+  if parts.len() != 4 || parts[0] != SCHEME {
+    return false;
+  }
+
+  let iterations = match parts[1].parse::<u32>() {
+    Ok(iterations) if iterations > 0 => iterations,
+    _ => return false,
+  };
+  let (salt, expected) = match (base64::decode(parts[2]), base64::decode(parts[3])) {
+    (Ok(salt), Ok(expected)) => (salt, expected),
+    _ => return false,
+  };
+
+  let actual = derive(password, &salt, iterations);
+  // constant time, so the comparison does not tell how much of the hash matched
+  actual.len() == expected.len()
+    && actual.iter().zip(expected.iter()).fold(0, |diff, (left, right)| diff | (left ^ right)) == 0
+}
+
+fn derive(password: &str, salt: &[u8], iterations: u32) -> [u8; HASH_LENGTH] {
+  let mut hash = [0u8; HASH_LENGTH];
+  pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, iterations, &mut hash);
+  hash
+}
+
+fn encode(iterations: u32, salt: &[u8], hash: &[u8]) -> String {
+  format!("{}${}${}${}", SCHEME, iterations, base64::encode(salt), base64::encode(hash))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test001_hashes_are_salted_and_verify() {
+    let first = hash("correct horse");
+    let second = hash("correct horse");
+    assert_ne!(first, second);
+
+    assert!(verify("correct horse", &first));
+    assert!(verify("correct horse", &second));
+    assert!(!verify("wrong horse", &first));
+    assert!(!verify("correct horse", "plain text"));
+  }
+
+  #[test]
+  fn test002_iterations_come_from_the_hash() {
+    let salt = [7u8; SALT_LENGTH];
+    let encoded = encode(10, &salt, &derive("secret", &salt, 10));
+    assert!(verify("secret", &encoded));
+    assert!(!verify("secret", &encoded.replacen("$10$", "$11$", 1)));
+  }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::proto::{OutputError, PresenceOutput, PresenceStatus};
+
+const CHANGES_CHANNEL_SIZE: usize = 256;
+const MAX_STATUS_TEXT_LENGTH: usize = 64;
+
+/// Who is connected, across the feeds and the rooms sockets.
+///
+/// A user is online from their first connection until their last one closes; in between they
+/// may switch to away or dnd with a custom text. Every change is published to the subscribers,
+/// the room and user storages, which push it to the sockets concerned.
+pub struct PresenceService {
+  users: Mutex<HashMap<Uuid, UserPresence>>,
+  changes: broadcast::Sender<PresenceOutput>,
+}
+
+struct UserPresence {
+  connections: usize,
+  status: PresenceStatus,
+  text: Option<String>,
+}
+
+impl Default for PresenceService {
+  fn default() -> Self {
+    let (changes, _) = broadcast::channel(CHANGES_CHANNEL_SIZE);
+    PresenceService {
+      users: Default::default(),
+      changes,
+    }
+  }
+}
+
+impl PresenceService {
+  pub fn subscribe(&self) -> broadcast::Receiver<PresenceOutput> {
+    self.changes.subscribe()
+  }
+
+  pub fn connect(&self, user_id: Uuid) {
+    let mut users = self.users.lock().unwrap();
+    let user = users.entry(user_id).or_insert(UserPresence { connections: 0, status: PresenceStatus::Online, text: None });
+    user.connections += 1;
+    if user.connections == 1 {
+      self.publish(PresenceOutput::new(user_id, user.status, user.text.clone()));
+    }
+  }
+
+  /// Drops one connection of the user; the last one takes them offline and forgets their status.
+  pub fn disconnect(&self, user_id: Uuid) {
+    let mut users = self.users.lock().unwrap();
+    let connections = match users.get_mut(&user_id) {
+      Some(user) => {
+        user.connections -= 1;
+        user.connections
+      },
+      None => return,
+    };
+    if connections == 0 {
+      users.remove(&user_id);
+      self.publish(PresenceOutput::new(user_id, PresenceStatus::Offline, None));
+    }
+  }
+
+  pub fn set_status(&self, user_id: Uuid, status: PresenceStatus, text: Option<String>) -> Result<(), OutputError> {
+    let text = text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+    if status == PresenceStatus::Offline || text.as_ref().map_or(0, String::len) > MAX_STATUS_TEXT_LENGTH {
+      return Err(OutputError::InvalidStatus);
+    }
+
+    let mut users = self.users.lock().unwrap();
+    let user = users.get_mut(&user_id).ok_or(OutputError::InvalidStatus)?;
+    if user.status != status || user.text != text {
+      user.status = status;
+      user.text = text;
+      self.publish(PresenceOutput::new(user_id, user.status, user.text.clone()));
+    }
+    Ok(())
+  }
+
+  pub fn presence(&self, user_id: Uuid) -> PresenceOutput {
+    match self.users.lock().unwrap().get(&user_id) {
+      Some(user) => PresenceOutput::new(user_id, user.status, user.text.clone()),
+      None => PresenceOutput::new(user_id, PresenceStatus::Offline, None),
+    }
+  }
+
+  fn publish(&self, presence: PresenceOutput) {
+    // nobody listening is fine
+    let _ = self.changes.send(presence);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test001_last_connection_takes_user_offline() {
+    let presence = PresenceService::default();
+    let mut changes = presence.subscribe();
+    let user_id = Uuid::new_v4();
+
+    presence.connect(user_id);
+    presence.connect(user_id);
+    presence.set_status(user_id, PresenceStatus::Away, Some(" lunch ".to_string())).unwrap();
+    presence.disconnect(user_id);
+    assert_eq!(presence.presence(user_id), PresenceOutput::new(user_id, PresenceStatus::Away, Some("lunch".to_string())));
+    presence.disconnect(user_id);
+
+    let statuses: Vec<PresenceStatus> = std::iter::from_fn(|| changes.try_recv().ok())
+      .map(|change| change.status)
+      .collect();
+    assert_eq!(statuses, vec![PresenceStatus::Online, PresenceStatus::Away, PresenceStatus::Offline]);
+    assert_eq!(presence.presence(user_id).status, PresenceStatus::Offline);
+  }
+
+  #[test]
+  fn test002_set_status_is_validated() {
+    let presence = PresenceService::default();
+    let user_id = Uuid::new_v4();
+
+    assert_eq!(presence.set_status(user_id, PresenceStatus::Dnd, None), Err(OutputError::InvalidStatus));
+
+    presence.connect(user_id);
+    assert_eq!(presence.set_status(user_id, PresenceStatus::Offline, None), Err(OutputError::InvalidStatus));
+    assert_eq!(presence.set_status(user_id, PresenceStatus::Dnd, Some("x".repeat(65))), Err(OutputError::InvalidStatus));
+    assert_eq!(presence.set_status(user_id, PresenceStatus::Dnd, Some("busy".to_string())), Ok(()));
+  }
+}
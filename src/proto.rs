@@ -26,6 +26,27 @@ pub enum Input {
 
   #[serde(rename = "post-message")]
   PostMessage(PostInput),
+
+  #[serde(rename = "edit-message")]
+  EditMessage(EditInput),
+
+  #[serde(rename = "delete-message")]
+  DeleteMessage(DeleteMessageInput),
+
+  #[serde(rename = "load-history")]
+  LoadHistory(LoadHistoryInput),
+
+  #[serde(rename = "set-status")]
+  SetStatus(SetStatusInput),
+
+  #[serde(rename = "typing-started")]
+  TypingStarted(TypingInput),
+
+  #[serde(rename = "typing-stopped")]
+  TypingStopped(TypingInput),
+
+  #[serde(rename = "mark-read")]
+  MarkRead(MarkReadInput),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -71,6 +92,78 @@ pub struct PostInput {
   pub body: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditInput {
+  pub client_id: Uuid,
+  pub message_id: Uuid,
+  pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessageInput {
+  pub client_id: Uuid,
+  pub message_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadHistoryInput {
+  pub client_id: Uuid,
+  /// `nextCursor` of the previous page; the newest messages when absent.
+  pub before: Option<String>,
+  pub limit: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetStatusInput {
+  pub client_id: Uuid,
+  pub status: PresenceStatus,
+  pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingInput {
+  pub client_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkReadInput {
+  pub client_id: Uuid,
+  pub message_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterInput {
+  pub username: String,
+  pub password: String,
+  /// The username when absent.
+  pub display_name: Option<String>,
+  pub avatar_url: Option<String>,
+  pub bio: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginInput {
+  pub username: String,
+  pub password: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+  Online,
+  Away,
+  Dnd,
+  Offline,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload")]
 pub enum Output {
@@ -110,6 +203,24 @@ pub enum Output {
   
   #[serde(rename = "user-posted")]
   UserPosted(UserPostedOutput),
+
+  #[serde(rename = "message-edited")]
+  MessageEdited(MessageEditedOutput),
+
+  #[serde(rename = "message-deleted")]
+  MessageDeleted(MessageDeletedOutput),
+
+  #[serde(rename = "history-loaded")]
+  HistoryLoaded(HistoryLoadedOutput),
+
+  #[serde(rename = "presence-changed")]
+  PresenceChanged(PresenceOutput),
+
+  #[serde(rename = "user-typing")]
+  UserTyping(UserTypingOutput),
+
+  #[serde(rename = "read-receipt")]
+  ReadReceipt(ReadReceiptOutput),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -130,10 +241,13 @@ pub enum OutputError {
 
   #[serde(rename = "remove-room-failed")]
   RemoveRoomFailed,
+
+  #[serde(rename = "create-room-failed")]
+  CreateRoomFailed,
   
   #[serde(rename = "user-name-taken")]
   UserNameTaken,
-  
+
   #[serde(rename = "invalid-user-name")]
   InvalidUserName,
   
@@ -142,6 +256,74 @@ pub enum OutputError {
   
   #[serde(rename = "invalid-message-body")]
   InvalidMessageBody,
+
+  #[serde(rename = "message-not-exists")]
+  MessageNotExists,
+
+  #[serde(rename = "not-message-author")]
+  NotMessageAuthor,
+
+  #[serde(rename = "delete-message-not-allowed")]
+  DeleteMessageNotAllowed,
+
+  #[serde(rename = "delete-message-failed")]
+  DeleteMessageFailed,
+
+  #[serde(rename = "edit-message-failed")]
+  EditMessageFailed,
+
+  #[serde(rename = "invalid-cursor")]
+  InvalidCursor,
+
+  #[serde(rename = "invalid-status")]
+  InvalidStatus,
+
+  #[serde(rename = "not-room-member")]
+  NotRoomMember,
+
+  #[serde(rename = "invalid-password")]
+  InvalidPassword,
+
+  #[serde(rename = "invalid-profile")]
+  InvalidProfile,
+
+  #[serde(rename = "invalid-credentials")]
+  InvalidCredentials,
+
+  #[serde(rename = "registration-failed")]
+  RegistrationFailed,
+
+  /// The input exists, but not on the socket it was sent to.
+  #[serde(rename = "unsupported-input")]
+  UnsupportedInput,
+
+  /// An HTTP request the API could not read: bad body, query or headers, or the wrong method.
+  #[serde(rename = "invalid-request")]
+  InvalidRequest,
+
+  /// An HTTP request for a path the server does not serve.
+  #[serde(rename = "not-found")]
+  NotFound,
+}
+
+impl Input {
+  /// The user an input says it comes from, for inputs that name one.
+  pub fn claimed_id(&self) -> Option<Uuid> {
+    match self {
+      Input::Ping | Input::DeleteRoom(_) => None,
+      Input::LoadRooms(input) => Some(input.user_id),
+      Input::LoadRoom(input) => Some(input.from_id),
+      Input::CreateRoom(input) => Some(input.host_id),
+      Input::JoinRoom(input) => Some(input.client_id),
+      Input::PostMessage(input) => Some(input.client_id),
+      Input::EditMessage(input) => Some(input.client_id),
+      Input::DeleteMessage(input) => Some(input.client_id),
+      Input::LoadHistory(input) => Some(input.client_id),
+      Input::SetStatus(input) => Some(input.client_id),
+      Input::TypingStarted(input) | Input::TypingStopped(input) => Some(input.client_id),
+      Input::MarkRead(input) => Some(input.client_id),
+    }
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -179,7 +361,7 @@ pub struct RoomsLoadedOutput {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RoomLoadedOutput {
-  pub users: Vec<UserOutput>,
+  pub users: Vec<MemberOutput>,
   pub recent_messages: Vec<MessageOutput>,
 }
 
@@ -202,6 +384,8 @@ pub struct RoomOutput {
   pub room_title: String,
   pub user_id: Uuid,
   pub create_at: DateTime<Utc>,
+  /// Messages from others since the member's read marker, at most `MAX_UNREAD_COUNT`.
+  pub unread_count: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -211,13 +395,30 @@ pub struct UserOutput {
   pub name: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberOutput {
+  pub id: Uuid,
+  pub name: String,
+  pub presence: PresenceOutput,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceOutput {
+  pub user_id: Uuid,
+  pub status: PresenceStatus,
+  pub text: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MessageOutput {
   pub id: Uuid,
   pub user: UserOutput,
   pub body: String,
-  // pub created_at: DateTime<Utc>,
+  pub created_at: DateTime<Utc>,
+  pub edited_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -254,6 +455,65 @@ pub struct UserPostedOutput {
   pub message: MessageOutput,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageEditedOutput {
+  pub room_id: String,
+  pub message: MessageOutput,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageDeletedOutput {
+  pub room_id: String,
+  pub message_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOutput {
+  pub id: Uuid,
+  pub username: String,
+  pub display_name: String,
+  pub avatar_url: Option<String>,
+  pub bio: Option<String>,
+  pub create_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginOutput {
+  /// Bearer token for the websocket upgrades.
+  pub token: String,
+  pub expires_at: DateTime<Utc>,
+  pub account: AccountOutput,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadReceiptOutput {
+  pub room_id: String,
+  pub user_id: Uuid,
+  pub message_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserTypingOutput {
+  pub room_id: String,
+  pub user_id: Uuid,
+  pub typing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryLoadedOutput {
+  pub room_id: String,
+  pub messages: Vec<MessageOutput>,
+  /// Opaque cursor for the next, older page; absent once the start of the room is reached.
+  pub next_cursor: Option<String>,
+}
+
 impl RoomsLoadedOutput {
   pub fn new(rooms: Vec<RoomOutput>) -> Self {
     RoomsLoadedOutput {
@@ -283,12 +543,14 @@ impl RoomOutput {
     room_id: String,
     room_title: String,
     user_id: Uuid,
-    create_at: DateTime<Utc>) -> Self {
+    create_at: DateTime<Utc>,
+    unread_count: usize) -> Self {
     RoomOutput {
       room_id,
       room_title,
       user_id,
-      create_at
+      create_at,
+      unread_count
     }
   }
 }
@@ -302,12 +564,30 @@ impl UserOutput {
   }
 }
 
+impl MemberOutput {
+  pub fn new(id: Uuid, name: &str, presence: PresenceOutput) -> Self {
+    MemberOutput {
+      id,
+      name: String::from(name),
+      presence,
+    }
+  }
+}
+
+impl PresenceOutput {
+  pub fn new(user_id: Uuid, status: PresenceStatus, text: Option<String>) -> Self {
+    PresenceOutput { user_id, status, text }
+  }
+}
+
 impl MessageOutput {
-  pub fn new(id: Uuid, user: UserOutput, body: &str) -> Self {
+  pub fn new(id: Uuid, user: UserOutput, body: &str, created_at: DateTime<Utc>) -> Self {
     MessageOutput {
       id,
       user,
       body: String::from(body),
+      created_at,
+      edited_at: None,
     }
   }
 }
@@ -345,3 +625,39 @@ impl UserPostedOutput {
     UserPostedOutput { message }
   }
 }
+
+impl MessageEditedOutput {
+  pub fn new(room_id: String, message: MessageOutput) -> Self {
+    MessageEditedOutput { room_id, message }
+  }
+}
+
+impl MessageDeletedOutput {
+  pub fn new(room_id: String, message_id: Uuid) -> Self {
+    MessageDeletedOutput { room_id, message_id }
+  }
+}
+
+impl UserTypingOutput {
+  pub fn new(room_id: String, user_id: Uuid, typing: bool) -> Self {
+    UserTypingOutput { room_id, user_id, typing }
+  }
+}
+
+impl LoginOutput {
+  pub fn new(token: String, expires_at: DateTime<Utc>, account: AccountOutput) -> Self {
+    LoginOutput { token, expires_at, account }
+  }
+}
+
+impl ReadReceiptOutput {
+  pub fn new(room_id: String, user_id: Uuid, message_id: Uuid) -> Self {
+    ReadReceiptOutput { room_id, user_id, message_id }
+  }
+}
+
+impl HistoryLoadedOutput {
+  pub fn new(room_id: String, messages: Vec<MessageOutput>, next_cursor: Option<String>) -> Self {
+    HistoryLoadedOutput { room_id, messages, next_cursor }
+  }
+}
@@ -0,0 +1,188 @@
+use std::convert::Infallible;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::Rejection;
+use warp::filters::body::BodyDeserializeError;
+use warp::http::StatusCode;
+use warp::reject;
+use warp::reply::{Reply, Response};
+use warp::ws::MissingConnectionUpgrade;
+
+use crate::auth::{self, TokenVerifier};
+use crate::proto::{Output, OutputError, UserOutput};
+
+/// Body of `POST /api/rooms/{room_id}`; the host is the caller.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRoomRequest {
+  pub room_title: String,
+  pub participants: Option<Vec<UserOutput>>,
+  pub delete_key: String,
+}
+
+/// Body of `DELETE /api/rooms/{room_id}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteRoomRequest {
+  pub delete_key: String,
+}
+
+/// Body of `POST /api/rooms/{room_id}/messages`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostMessageRequest {
+  pub body: String,
+}
+
+/// Query of `GET /api/rooms/{room_id}/messages`, the same cursor the websockets page with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryQuery {
+  pub before: Option<String>,
+  pub limit: Option<i32>,
+}
+
+/// The status an error output is answered with.
+pub fn error_status(error: &OutputError) -> StatusCode {
+  match error {
+    OutputError::RoomNotExists |
+    OutputError::MessageNotExists |
+    OutputError::NotFound => StatusCode::NOT_FOUND,
+    OutputError::RoomNameTaken |
+    OutputError::UserNameTaken => StatusCode::CONFLICT,
+    OutputError::NotRoomMember |
+    OutputError::NotMessageAuthor |
+    OutputError::DeleteMessageNotAllowed |
+    OutputError::RemoveRoomFailed |
+    OutputError::UserNotJoined => StatusCode::FORBIDDEN,
+    OutputError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+    OutputError::RegistrationFailed |
+    OutputError::CreateRoomFailed |
+    OutputError::DeleteMessageFailed |
+    OutputError::EditMessageFailed => StatusCode::INTERNAL_SERVER_ERROR,
+    OutputError::HostUserNotExists |
+    OutputError::InvalidRoomName |
+    OutputError::InvalidUserName |
+    OutputError::InvalidMessageBody |
+    OutputError::InvalidCursor |
+    OutputError::InvalidStatus |
+    OutputError::InvalidPassword |
+    OutputError::InvalidProfile |
+    OutputError::UnsupportedInput |
+    OutputError::InvalidRequest => StatusCode::BAD_REQUEST,
+  }
+}
+
+/// The error output the websockets would send, with a matching status.
+pub fn error_reply(error: OutputError) -> Response {
+  let status = error_status(&error);
+  error_reply_with_status(error, status)
+}
+
+fn error_reply_with_status(error: OutputError, status: StatusCode) -> Response {
+  warp::reply::with_status(warp::reply::json(&Output::Error(error)), status).into_response()
+}
+
+/// Answers whatever no route took with a JSON error too. The rejections of every route tried
+/// are combined, so what is wrong with the request itself is looked for before a method some
+/// other route wanted, and a path nobody serves comes last.
+pub async fn recover(rejection: Rejection) -> Result<Response, Infallible> {
+  let status = if rejection.find::<BodyDeserializeError>().is_some()
+    || rejection.find::<reject::InvalidQuery>().is_some()
+    || rejection.find::<reject::MissingHeader>().is_some()
+    || rejection.find::<reject::InvalidHeader>().is_some()
+    || rejection.find::<MissingConnectionUpgrade>().is_some() {
+    StatusCode::BAD_REQUEST
+  } else if rejection.find::<reject::LengthRequired>().is_some() {
+    StatusCode::LENGTH_REQUIRED
+  } else if rejection.find::<reject::PayloadTooLarge>().is_some() {
+    StatusCode::PAYLOAD_TOO_LARGE
+  } else if rejection.find::<reject::UnsupportedMediaType>().is_some() {
+    StatusCode::UNSUPPORTED_MEDIA_TYPE
+  } else if rejection.find::<reject::MethodNotAllowed>().is_some() {
+    StatusCode::METHOD_NOT_ALLOWED
+  } else if rejection.is_not_found() {
+    return Ok(error_reply(OutputError::NotFound));
+  } else {
+    warn!("unexpected rejection: {:?}", rejection);
+    StatusCode::BAD_REQUEST
+  };
+  Ok(error_reply_with_status(OutputError::InvalidRequest, status))
+}
+
+/// `output` as JSON with `status`, or the error reply.
+pub fn reply<T: Serialize>(result: Result<T, OutputError>, status: StatusCode) -> Response {
+  match result {
+    Ok(output) => warp::reply::with_status(warp::reply::json(&output), status).into_response(),
+    Err(error) => error_reply(error),
+  }
+}
+
+/// The caller of an API request; a missing or bad token is answered like a failed login.
+pub fn authenticate(verifier: &dyn TokenVerifier, token: Option<String>) -> Result<Uuid, OutputError> {
+  auth::authenticate(verifier, token).map_err(|_| OutputError::InvalidCredentials)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::{Duration, Utc};
+  use crate::auth::{JwtVerifier, TokenIssuer};
+  use warp::Filter;
+
+  macro_rules! aw {
+      ($e:expr) => {
+          tokio_test::block_on($e)
+      };
+  }
+
+  #[test]
+  fn test001_errors_reply_with_json_and_matching_status() {
+    let response = error_reply(OutputError::NotRoomMember);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.headers()["content-type"], "application/json");
+
+    assert_eq!(reply::<()>(Err(OutputError::RoomNotExists), StatusCode::OK).status(), StatusCode::NOT_FOUND);
+    assert_eq!(reply(Ok(()), StatusCode::CREATED).status(), StatusCode::CREATED);
+  }
+
+  #[test]
+  fn test002_bad_tokens_are_invalid_credentials() {
+    let verifier = JwtVerifier::new("secret");
+    let user_id = Uuid::new_v4();
+    let token = verifier.issue(user_id, Utc::now() + Duration::minutes(5));
+
+    assert_eq!(authenticate(&verifier, Some(token)), Ok(user_id));
+    assert_eq!(authenticate(&verifier, Some("garbage".to_string())), Err(OutputError::InvalidCredentials));
+    assert_eq!(authenticate(&verifier, None), Err(OutputError::InvalidCredentials));
+  }
+
+  #[test]
+  fn test003_rejections_reply_with_json_errors() {
+    let post = warp::path!("rooms" / String / "messages")
+      .and(warp::post())
+      .and(warp::body::json())
+      .map(|_: String, _: PostMessageRequest| warp::reply().into_response());
+    let history = warp::path!("rooms" / String / "messages")
+      .and(warp::get())
+      .and(warp::query())
+      .map(|_: String, _: HistoryQuery| warp::reply().into_response());
+    let routes = post.or(history).unify().recover(recover);
+
+    let bad_body = aw!(warp::test::request().method("POST").path("/rooms/lobby/messages").body("{").reply(&routes));
+    assert_eq!(bad_body.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(bad_body.headers()["content-type"], "application/json");
+    assert_eq!(bad_body.body(), &serde_json::to_vec(&Output::Error(OutputError::InvalidRequest)).unwrap());
+
+    let bad_query = aw!(warp::test::request().path("/rooms/lobby/messages?limit=many").reply(&routes));
+    assert_eq!(bad_query.status(), StatusCode::BAD_REQUEST);
+
+    let wrong_method = aw!(warp::test::request().method("PUT").path("/rooms/lobby/messages").reply(&routes));
+    assert_eq!(wrong_method.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let unknown = aw!(warp::test::request().path("/nowhere").reply(&routes));
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    assert_eq!(unknown.body(), &serde_json::to_vec(&Output::Error(OutputError::NotFound)).unwrap());
+  }
+}
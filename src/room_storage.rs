@@ -1,142 +1,238 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use futures::StreamExt;
+use std::time::{Duration, Instant};
+use futures::{future, StreamExt};
+use log::{error, info, warn};
 use uuid::Uuid;
 use chrono::Utc;
-use tokio::sync::{ RwLock, broadcast, mpsc::UnboundedReceiver };
+use tokio::runtime::Handle;
+use tokio::sync::{ RwLock, broadcast::{self, RecvError}, mpsc::{Receiver, UnboundedReceiver} };
+use tokio::time;
 
 use crate::domain::room_repository::RoomRepository;
 use crate::domain::user_repository::UserRepository;
 use crate::domain::message_repository::MessageRepository;
 use crate::domain::room_user_repository::RoomUserRepository;
-use crate::domain::repository::RepositoryFactory;
+use crate::domain::repository::Repositories;
 use crate::model::{room::Room, user::User};
 use crate::model::room_user::RoomUser;
-use crate::hub::Hub;
+use crate::hub::{Hub, HubOptions};
 use crate::proto::*;
-use crate::utils::AppUtils;
-
-const OUTPUT_CHANNEL_SIZE: usize = 256;
+use crate::session::{SessionId, SessionRegistry};
+use crate::presence::PresenceService;
 
 pub struct RoomStorage {
-  output_sender: broadcast::Sender<OutputParcel>,
+  sessions: Arc<SessionRegistry>,
+  presence: Arc<PresenceService>,
   rooms: RwLock<HashMap<String, Arc<Room>>>,
   hubs: RwLock<HashMap<String, Arc<Hub>>>,
-  // hub_options: Option<HubOptions>,
+  hub_options: HubOptions,
+  // rooms without any connection at the last sweep, and since when
+  idle_since: RwLock<HashMap<String, Instant>>,
+  hub_idle_timeout: Duration,
+
+  room_repository: Arc<dyn RoomRepository>,
+  user_repository: Arc<dyn UserRepository>,
+  message_repository: Arc<dyn MessageRepository>,
+  room_user_repository: Arc<dyn RoomUserRepository>,
+}
 
-  room_repository: Arc<RoomRepository>,
-  user_repository: Arc<UserRepository>,
-  message_repository: Arc<MessageRepository>,
-  room_user_repository: Arc<RoomUserRepository>,
+/// A websocket attached to a room. However the connection ends, dropping it unregisters its
+/// session and its presence and, once the client has no other connection to the room, lets the
+/// hub know it left.
+pub struct RoomConnection {
+  storage: Arc<RoomStorage>,
+  room_id: String,
+  session_id: SessionId,
+  // kept here too, the registry forgets sessions it drops for not reading
+  client_id: Uuid,
 }
 
 impl RoomStorage {
-  pub fn new(repo_fact: &RepositoryFactory) -> Self {
-    let (output_sender, _) = broadcast::channel(OUTPUT_CHANNEL_SIZE);
-
-    let room_repository = match AppUtils::downcast_arc::<RoomRepository>(
-      repo_fact.get_repository("ROOM")) {
-      Ok(repo) => repo,
-      Err(_) => panic!("can't find repository")
-    };
-
-    let user_repository = match AppUtils::downcast_arc::<UserRepository>(
-      repo_fact.get_repository("USER")) {
-      Ok(repo) => repo,
-      Err(_) => panic!("can't find repository")
-    };
-
-    let message_repository = match AppUtils::downcast_arc::<MessageRepository>(
-      repo_fact.get_repository("MESSAGE")) {
-      Ok(repo) => repo,
-      Err(_) => panic!("can't find repository")
-    };
-
-    let room_user_repository = match AppUtils::downcast_arc::<RoomUserRepository>(
-      repo_fact.get_repository("ROOM_USERS")) {
-      Ok(repo) => repo,
-      Err(_) => panic!("can't find repository")
-    };
+  pub fn new(repositories: &Repositories, presence: Arc<PresenceService>, output_channel_size: usize,
+             hub_options: HubOptions, hub_idle_timeout: Duration) -> Self {
 
     RoomStorage {
-      output_sender,
+      sessions: Arc::new(SessionRegistry::new(output_channel_size)),
+      presence,
       rooms: Default::default(),
       hubs: Default::default(),
-      // hub_options,
-      room_repository,
-      user_repository,
-      message_repository,
-      room_user_repository,
+      hub_options,
+      idle_since: Default::default(),
+      hub_idle_timeout,
+      room_repository: repositories.room(),
+      user_repository: repositories.user(),
+      message_repository: repositories.message(),
+      room_user_repository: repositories.room_user(),
     }
   }
 
+  // the REST handlers call in from their own tasks besides `run`; whatever is inserted is
+  // looked up again under the same write guard, so racing callers share one instance
+
   async fn get_room(&self, room_id: &str) -> Option<Arc<Room>> {
-    if self.rooms.read().await.contains_key(room_id) {
-      let rooms = self.rooms.read().await;
-      let room = rooms.get(room_id).unwrap();
-      Some(Arc::clone(room))
-    }
-    else if let Some(room) = self.room_repository.load_one_room(room_id).await {
-      self.rooms
-          .write()
-          .await
-          .insert(room_id.to_string(), Arc::new(room))
-    }
-    else {
-      None
+    if let Some(room) = self.rooms.read().await.get(room_id) {
+      return Some(Arc::clone(room));
     }
+
+    let room = self.room_repository.load_one_room(room_id).await?;
+    let mut rooms = self.rooms.write().await;
+    Some(Arc::clone(rooms.entry(room_id.to_string()).or_insert_with(|| Arc::new(room))))
   }
 
   async fn get_hub(&self, room_id: &str) -> Option<Arc<Hub>> {
-    if !self.hubs.read().await.contains_key(room_id) {
-      let mut hubs = self.hubs.write().await;
-      hubs.insert(room_id.to_string(), self.new_hub())
+    if let Some(hub) = self.hubs.read().await.get(room_id) {
+      return Some(Arc::clone(hub));
     }
-    else {
-      let hubs = self.hubs.read().await;
-      let hub = hubs.get(room_id).unwrap();
-      Some(Arc::clone(hub))
+
+    let mut hubs = self.hubs.write().await;
+    Some(Arc::clone(hubs.entry(room_id.to_string()).or_insert_with(|| self.new_hub())))
+  }
+
+  pub fn sessions(&self) -> Arc<SessionRegistry> {
+    Arc::clone(&self.sessions)
+  }
+
+  pub fn connect(storage: &Arc<RoomStorage>, room_id: &str, client_id: Uuid) -> (RoomConnection, Receiver<OutputParcel>) {
+    let (session_id, receiver) = storage.sessions.register(room_id, client_id);
+    storage.presence.connect(client_id);
+    let connection = RoomConnection {
+      storage: Arc::clone(storage),
+      room_id: room_id.to_string(),
+      session_id,
+      client_id,
+    };
+    (connection, receiver)
+  }
+
+  /// Tells the room's hub that a client is gone, so the others see it leave.
+  pub async fn on_disconnect(&self, room_id: &str, client_id: Uuid) {
+    let hub = self.hubs.read().await.get(room_id).cloned();
+    if let Some(hub) = hub {
+      hub.on_disconnect(room_id, client_id).await;
     }
   }
 
-  pub fn subscribe(&self) -> broadcast::Receiver<OutputParcel> {
-    self.output_sender.subscribe()
-  }
-
-  // async fn tick_alive(&self) {
-  //   let alive_interval = if let Some(alive_interval) = self.hub_options.unwrap().alive_interval {
-  //     alive_interval
-  //   } else {
-  //     return;
-  //   };
-  //
-  //   loop {
-  //     time::delay_for(alive_interval).await;
-  //     self.rooms.read().await.keys().for_each(|room_id| {
-  //       self.output_sender
-  //         .send(OutputParcel::new(
-  //           String::from(room_id), Default::default(), Output::Alive))
-  //         .unwrap();
-  //     })
-  //   }
-  // }
+  async fn tick_alive(&self) {
+    let alive_interval = match self.hub_options.alive_interval {
+      Some(alive_interval) => alive_interval,
+      None => return future::pending().await,
+    };
+
+    let mut interval = time::interval(alive_interval);
+    loop {
+      interval.tick().await;
+      for room_id in self.sessions.rooms() {
+        self.sessions.send(OutputParcel::new(room_id, Uuid::default(), Output::Alive));
+      }
+    }
+  }
 
   pub async fn run(&self, receiver: UnboundedReceiver<InputParcel>) {
-    // let ticking_alive = self.tick_alive();
+    let ticking_alive = self.tick_alive();
+    let evicting = self.evict_idle_hubs();
+    let expiring_typing = self.expire_typing();
+    let relaying = self.relay_presence(self.presence.subscribe());
     let processing = receiver.for_each(|input_parcel| self.process(input_parcel));
 
     tokio::select! {
-      // _ = ticking_alive => {},
+      _ = ticking_alive => {},
+      _ = evicting => {},
+      _ = expiring_typing => {},
+      _ = relaying => {},
       _ = processing => {},
     }
   }
 
+  async fn expire_typing(&self) {
+    let mut interval = time::interval(Duration::from_secs(1));
+    loop {
+      interval.tick().await;
+      let hubs: Vec<(String, Arc<Hub>)> = self.hubs.read().await
+        .iter()
+        .map(|(room_id, hub)| (room_id.clone(), Arc::clone(hub)))
+        .collect();
+
+      let now = Instant::now();
+      for (room_id, hub) in hubs {
+        hub.expire_typing(&room_id, now).await;
+      }
+    }
+  }
+
+  async fn relay_presence(&self, mut changes: broadcast::Receiver<PresenceOutput>) {
+    loop {
+      match changes.recv().await {
+        Ok(presence) => self.on_presence_changed(presence).await,
+        Err(RecvError::Lagged(skipped)) => warn!("dropped {} presence changes", skipped),
+        Err(RecvError::Closed) => return,
+      }
+    }
+  }
+
+  /// Pushes `presence-changed` to every loaded room the user is a member of.
+  async fn on_presence_changed(&self, presence: PresenceOutput) {
+    let hubs: Vec<(String, Arc<Hub>)> = self.hubs.read().await
+      .iter()
+      .map(|(room_id, hub)| (room_id.clone(), Arc::clone(hub)))
+      .collect();
+
+    for (room_id, hub) in hubs {
+      hub.on_presence_changed(&room_id, presence.clone()).await;
+    }
+  }
+
+  async fn evict_idle_hubs(&self) {
+    let mut interval = time::interval((self.hub_idle_timeout / 2).max(Duration::from_secs(1)));
+    loop {
+      interval.tick().await;
+      self.sweep_idle_hubs(Instant::now()).await;
+    }
+  }
+
+  /// Drops the rooms that have had no connection for `hub_idle_timeout`, with their users and
+  /// feed; the next `load-room` rebuilds them from storage.
+  async fn sweep_idle_hubs(&self, now: Instant) {
+    let mut room_ids: Vec<String> = self.hubs.read().await.keys().cloned().collect();
+    room_ids.extend(self.rooms.read().await.keys().cloned());
+    room_ids.sort();
+    room_ids.dedup();
+
+    let mut idle_since = self.idle_since.write().await;
+    idle_since.retain(|room_id, _| room_ids.contains(room_id));
+
+    for room_id in room_ids {
+      if self.sessions.session_count(&room_id) > 0 {
+        idle_since.remove(&room_id);
+        continue;
+      }
+
+      let since = *idle_since.entry(room_id.clone()).or_insert(now);
+      if now.duration_since(since) >= self.hub_idle_timeout {
+        idle_since.remove(&room_id);
+        self.hubs.write().await.remove(&room_id);
+        self.rooms.write().await.remove(&room_id);
+        info!("evicted idle room {}", room_id);
+      }
+    }
+  }
+
   async fn process(&self, input_parcel: InputParcel) {
     match input_parcel.input {
       Input::Ping => self.send_pong(input_parcel),
       Input::LoadRoom(input) => self.load_room(input_parcel.room_id, input).await,
-      Input::CreateRoom(room_input) => self.create_room(input_parcel.room_id, room_input).await,
-      Input::DeleteRoom(remove_room_input) => self.delete_room(remove_room_input).await,
+      Input::CreateRoom(room_input) => {
+        if let Err(error) = self.create_room(input_parcel.room_id.clone(), room_input).await {
+          self.send_error(input_parcel.room_id.as_str(), error);
+        }
+      },
+      Input::DeleteRoom(remove_room_input) => {
+        let room_id = remove_room_input.room_id.clone();
+        if let Err(error) = self.delete_room(remove_room_input).await {
+          self.send_error(room_id.as_str(), error);
+        }
+      },
+      Input::SetStatus(input) => self.set_status(input_parcel.room_id, input_parcel.client_id, input),
       _ => match self.get_hub(input_parcel.room_id.as_str()).await {
         Some(hub) => {
           hub.process(input_parcel).await;
@@ -147,9 +243,14 @@ impl RoomStorage {
   }
 
   fn send_pong(&self, input_parcel: InputParcel) {
-    self.output_sender
-        .send(OutputParcel::new(input_parcel.room_id, input_parcel.client_id, Output::Pong))
-        .unwrap();
+    self.sessions
+        .send(OutputParcel::new(input_parcel.room_id, input_parcel.client_id, Output::Pong));
+  }
+
+  fn set_status(&self, room_id: String, client_id: Uuid, input: SetStatusInput) {
+    if let Err(error) = self.presence.set_status(client_id, input.status, input.text) {
+      self.sessions.send(OutputParcel::new(room_id, client_id, Output::Error(error)));
+    }
   }
 
   async fn load_room(&self, room_id: String, input: LoadRoomInput) {
@@ -179,13 +280,9 @@ impl RoomStorage {
     }
   }
 
-  async fn create_room(&self, room_id: String, input: RoomInput) {
-
-    // check room_id exits
-    if self.rooms.read().await.contains_key(room_id.as_str()) {
-      self.send_error(room_id.as_str(), OutputError::RoomNameTaken);
-      return;
-    }
+  /// Creates the room with its host and participants as members, and tells the sockets
+  /// already waiting in it.
+  pub async fn create_room(&self, room_id: String, input: RoomInput) -> Result<RoomCreatedOutput, OutputError> {
 
     // TODO: check room_id valid
 
@@ -200,13 +297,40 @@ impl RoomStorage {
 
     let room = Room::new(room_id.clone(), input.room_title, input.host_id, input.host_name.clone(),
                          users.clone(), Utc::now(), input.delete_key);
-    self.rooms.write().await.insert(room_id.clone(), Arc::new(room.clone()));
 
-    // create Hub
-    let hub = Hub::new(self.output_sender.clone(),
-                       Arc::clone(&self.user_repository),
-                       Arc::clone(&self.message_repository),
-                       Arc::clone(&self.room_user_repository));
+    // check room_id exits; the repository only stores the room if nobody else has meanwhile
+    if self.rooms.read().await.contains_key(room_id.as_str()) || self.room_repository.room_exists(room_id.as_str()).await {
+      return Err(OutputError::RoomNameTaken);
+    }
+    let room = match self.room_repository.create_room(room).await {
+      Some(room) => room,
+      None if self.room_repository.room_exists(room_id.as_str()).await => return Err(OutputError::RoomNameTaken),
+      None => return Err(OutputError::CreateRoomFailed),
+    };
+
+    // serve members to database; the room is no use without its host
+    let host = RoomUser::new(room_id.clone(), room.room_title.clone(), input.host_id, input.host_name.clone(),
+                             room.create_at);
+    if self.room_user_repository.create_room_users(host).await.is_none() {
+      error!("failed to add host {} to room {}", input.host_id, room_id);
+      self.room_repository.delete_room(room_id.as_str()).await.ok();
+      return Err(OutputError::CreateRoomFailed);
+    }
+    for user in users.iter().flatten() {
+      let room_user = RoomUser::new(room_id.clone(), room.room_title.clone(), user.id, user.name.clone(),
+                                    room.create_at);
+      if self.room_user_repository.create_room_users(room_user).await.is_none() {
+        error!("failed to add participant {} to room {}", user.id, room_id);
+      }
+    }
+
+    // whoever loaded the stored room meanwhile already cached the same one
+    self.rooms.write().await
+      .entry(room_id.clone())
+      .or_insert_with(|| Arc::new(room));
+
+    // the hub sockets waiting in the room already share, if any
+    let hub = self.get_hub(room_id.as_str()).await.ok_or(OutputError::RoomNotExists)?;
 
     // invite host
     let host_input = InputParcel::new(input.host_id, room_id.clone(),
@@ -231,82 +355,109 @@ impl RoomStorage {
       }
     }
 
-    // serve hub instance
-    self.hubs.write().await.insert(room_id.clone(), Arc::new(hub));
-
-    // serve room to database
-    if let Some(room) = self.room_repository.create_room(room).await {
-      let mut users = vec![];
-      users.push(room.host_info);
-      if let Some(mut parts) = room.participants {
-        users.append(&mut parts);
-      }
-
-      for user in users {
-        let room_user = RoomUser::new(
-          room.room_id.clone(),
-          room.room_title.clone(),
-          user.id,
-          user.name,
-          room.create_at,
-        );
-        self.room_user_repository.create_room_users(room_user).await;
-      }
-    }
-
     // send created notification
-    self.output_sender
-      .send(OutputParcel::new(room_id.clone(), Default::default(),
-                              Output::RoomCreated(RoomCreatedOutput::new(room_id))))
-      .unwrap();
+    let created = RoomCreatedOutput::new(room_id.clone());
+    self.sessions
+      .send(OutputParcel::new(room_id, Default::default(), Output::RoomCreated(created.clone())));
+    Ok(created)
   }
 
-  async fn delete_room(&self, remove_room_input: RemoveRoomInput) {
+  /// Deletes the room and its memberships when the key matches, and tells its sockets.
+  pub async fn delete_room(&self, remove_room_input: RemoveRoomInput) -> Result<RoomRemovedOutput, OutputError> {
     let room_id = remove_room_input.room_id;
     let delete_key = remove_room_input.delete_key;
 
-    if let Some(room) = self.get_room(room_id.as_str()).await {
-      if room.delete_key != delete_key {
-        self.send_error(room_id.as_str(), OutputError::RemoveRoomFailed);
-      }
-    } else {
-      self.send_error(room_id.as_str(), OutputError::RoomNotExists);
-      return;
+    match self.get_room(room_id.as_str()).await {
+      Some(room) if room.delete_key != delete_key => return Err(OutputError::RemoveRoomFailed),
+      Some(_) => {},
+      None => return Err(OutputError::RoomNotExists),
     }
 
-    // delete room instance
-    self.rooms.write().await.remove(room_id.as_str()).unwrap();
-    let hub = self.hubs.write().await.remove(room_id.as_str()).unwrap();
-    drop(hub);
+    // delete room instance; the hub is only there while the room is loaded
+    self.rooms.write().await.remove(room_id.as_str());
+    self.hubs.write().await.remove(room_id.as_str());
 
     // send removed notification
-    self.output_sender
-      .send(OutputParcel::new(room_id.clone(), Default::default(),
-                              Output::RoomRemoved(
-                                RoomRemovedOutput::new(room_id.clone()))))
-      .unwrap();
+    let removed = RoomRemovedOutput::new(room_id.clone());
+    self.sessions
+      .send(OutputParcel::new(room_id.clone(), Default::default(), Output::RoomRemoved(removed.clone())));
 
     // delete room from db
+    self.room_user_repository.delete_by_room(room_id.clone()).await.ok();
     self.room_repository.delete_room(room_id.as_str()).await.ok();
+    Ok(removed)
+  }
+
+  /// History for a member of the room, whether or not they have joined its feed.
+  pub async fn load_history(&self, room_id: &str, user_id: Uuid, before: Option<&str>, limit: Option<i32>)
+    -> Result<HistoryLoadedOutput, OutputError> {
+    let hub = self.member_hub(room_id, user_id).await?.1;
+    hub.load_history(room_id, before, limit).await
+  }
+
+  /// Posts for a member of the room; its sockets get the message like any other.
+  pub async fn post_message(&self, room_id: &str, user_id: Uuid, body: &str) -> Result<MessageOutput, OutputError> {
+    let (member, hub) = self.member_hub(room_id, user_id).await?;
+    hub.post_message(room_id, User::new(user_id, &member.username), body).await
+  }
+
+  async fn member_hub(&self, room_id: &str, user_id: Uuid) -> Result<(RoomUser, Arc<Hub>), OutputError> {
+    if self.get_room(room_id).await.is_none() {
+      return Err(OutputError::RoomNotExists);
+    }
+
+    let member = self.room_user_repository.load_one(String::from(room_id), user_id).await
+      .ok_or(OutputError::NotRoomMember)?;
+    let hub = self.get_hub(room_id).await.ok_or(OutputError::RoomNotExists)?;
+    Ok((member, hub))
   }
 
   fn new_hub(&self) -> Arc<Hub> {
     Arc::new(
-      Hub::new(self.output_sender.clone(),
+      Hub::new(Arc::clone(&self.sessions),
+               Arc::clone(&self.presence),
              Arc::clone(&self.user_repository),
+               Arc::clone(&self.room_user_repository),
                Arc::clone(&self.message_repository),
-               Arc::clone(&self.room_user_repository) )
+               self.hub_options)
     )
   }
 
   fn send_error(&self, room_id: &str, error: OutputError) {
-    self.output_sender
+    self.sessions
       .send(OutputParcel::new(String::from(room_id),
-                              Default::default(), Output::Error(error)))
-      .unwrap();
+                              Default::default(), Output::Error(error)));
+  }
+}
+
+
+impl RoomConnection {
+  pub fn session_id(&self) -> SessionId {
+    self.session_id
   }
 }
 
+impl Drop for RoomConnection {
+  fn drop(&mut self) {
+    let sessions = &self.storage.sessions;
+    sessions.unregister(self.session_id);
+
+    let client_id = self.client_id;
+    self.storage.presence.disconnect(client_id);
+    if sessions.client_session_count(&self.room_id, client_id) > 0 {
+      return;
+    }
+
+    let storage = Arc::clone(&self.storage);
+    let room_id = self.room_id.clone();
+    match Handle::try_current() {
+      Ok(handle) => {
+        handle.spawn(async move { storage.on_disconnect(&room_id, client_id).await });
+      },
+      Err(_) => warn!("no runtime to report client {} leaving room {}", client_id, room_id),
+    }
+  }
+}
 
 #[cfg(test)]
 mod tests {
@@ -332,4 +483,147 @@ mod tests {
     let num = aw!(numb.read());
     println!("{}", *num);
   }
+
+  #[test]
+  fn test001_idle_hubs_are_evicted_and_reloaded() {
+    let timeout = Duration::from_secs(60);
+    let storage = RoomStorage::new(&Repositories::in_memory(), Default::default(), 16, HubOptions::default(), timeout);
+    let host_id = Uuid::new_v4();
+    aw!(storage.process(InputParcel::new(host_id, "room".to_string(), Input::CreateRoom(RoomInput {
+      room_title: "title".to_string(),
+      host_id,
+      host_name: "host".to_string(),
+      participants: None,
+      delete_key: "key".to_string(),
+    }))));
+
+    // a connected room is never idle
+    let now = Instant::now();
+    let (session_id, _receiver) = storage.sessions.register("room", host_id);
+    aw!(storage.sweep_idle_hubs(now));
+    aw!(storage.sweep_idle_hubs(now + timeout));
+    assert!(aw!(storage.hubs.read()).contains_key("room"));
+
+    storage.sessions.unregister(session_id);
+    aw!(storage.sweep_idle_hubs(now + timeout));
+    assert!(aw!(storage.hubs.read()).contains_key("room"));
+    aw!(storage.sweep_idle_hubs(now + timeout * 2));
+    assert!(aw!(storage.hubs.read()).is_empty());
+    assert!(aw!(storage.rooms.read()).is_empty());
+
+    // the next load-room rebuilds the hub from storage
+    let (_, mut receiver) = storage.sessions.register("room", Uuid::nil());
+    aw!(storage.process(InputParcel::new(host_id, "room".to_string(), Input::LoadRoom(LoadRoomInput { from_id: host_id }))));
+    match receiver.try_recv().unwrap().output {
+      Output::RoomLoaded(loaded) => assert_eq!(loaded.users, vec![
+        MemberOutput::new(host_id, "host", PresenceOutput::new(host_id, PresenceStatus::Offline, None))]),
+      other => panic!("unexpected output {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test002_last_closed_connection_reports_user_left() {
+    let storage = Arc::new(RoomStorage::new(&Repositories::in_memory(), Default::default(), 16, HubOptions::default(),
+                                            Duration::from_secs(60)));
+    let host_id = Uuid::new_v4();
+    let alice_id = Uuid::new_v4();
+    aw!(storage.process(InputParcel::new(host_id, "room".to_string(), Input::CreateRoom(RoomInput {
+      room_title: "title".to_string(),
+      host_id,
+      host_name: "host".to_string(),
+      participants: None,
+      delete_key: "key".to_string(),
+    }))));
+
+    let (_host, mut host_outputs) = RoomStorage::connect(&storage, "room", host_id);
+    let (first, _first_outputs) = RoomStorage::connect(&storage, "room", alice_id);
+    let (second, _second_outputs) = RoomStorage::connect(&storage, "room", alice_id);
+    aw!(storage.process(InputParcel::new(alice_id, "room".to_string(),
+                                         Input::JoinRoom(JoinInput { client_id: alice_id, name: "alice".to_string() }))));
+    assert!(matches!(host_outputs.try_recv().unwrap().output, Output::UserJoined(_)));
+
+    // dropping the connections spawns the report, which runs while the test waits
+    aw!(async {
+      drop(first);
+      time::delay_for(Duration::from_millis(10)).await;
+    });
+    assert!(host_outputs.try_recv().is_err());
+
+    aw!(async {
+      drop(second);
+      time::delay_for(Duration::from_millis(10)).await;
+    });
+    assert_eq!(host_outputs.try_recv().unwrap().output,
+               Output::UserLeft(UserLeftOutput::new("room".to_string(), alice_id)));
+  }
+
+  #[test]
+  fn test003_members_post_over_rest_to_the_sockets() {
+    let storage = Arc::new(RoomStorage::new(&Repositories::in_memory(), Default::default(), 16, HubOptions::default(),
+                                            Duration::from_secs(60)));
+    let host_id = Uuid::new_v4();
+    let input = RoomInput {
+      room_title: "title".to_string(),
+      host_id,
+      host_name: "host".to_string(),
+      participants: None,
+      delete_key: "key".to_string(),
+    };
+    assert!(aw!(storage.create_room("room".to_string(), input.clone())).is_ok());
+    assert_eq!(aw!(storage.create_room("room".to_string(), input)), Err(OutputError::RoomNameTaken));
+
+    let (_host, mut host_outputs) = RoomStorage::connect(&storage, "room", host_id);
+    let posted = aw!(storage.post_message("room", host_id, "hello")).unwrap();
+    match host_outputs.try_recv().unwrap().output {
+      Output::UserPosted(output) => assert_eq!(output.message, posted),
+      other => panic!("unexpected output {:?}", other),
+    }
+    assert_eq!(aw!(storage.load_history("room", host_id, None, None)).unwrap().messages, vec![posted]);
+
+    let stranger_id = Uuid::new_v4();
+    assert_eq!(aw!(storage.post_message("room", stranger_id, "hi")), Err(OutputError::NotRoomMember));
+    assert_eq!(aw!(storage.load_history("room", stranger_id, None, None)), Err(OutputError::NotRoomMember));
+    assert_eq!(aw!(storage.post_message("other", host_id, "hi")), Err(OutputError::RoomNotExists));
+
+    let remove = |delete_key: &str| RemoveRoomInput { room_id: "room".to_string(), delete_key: delete_key.to_string() };
+    assert_eq!(aw!(storage.delete_room(remove("wrong"))), Err(OutputError::RemoveRoomFailed));
+    assert!(aw!(storage.delete_room(remove("key"))).is_ok());
+    assert_eq!(aw!(storage.delete_room(remove("key"))), Err(OutputError::RoomNotExists));
+  }
+
+  #[test]
+  fn test004_rooms_are_cached_once_stored_with_their_members() {
+    let repositories = Repositories::in_memory();
+    let storage = RoomStorage::new(&repositories, Default::default(), 16, HubOptions::default(), Duration::from_secs(60));
+    let (_, mut outputs) = storage.sessions.register("room", Uuid::nil());
+    let host_id = Uuid::new_v4();
+    let guest_id = Uuid::new_v4();
+    let input = RoomInput {
+      room_title: "title".to_string(),
+      host_id,
+      host_name: "host".to_string(),
+      participants: Some(vec![UserOutput::new(guest_id, "guest")]),
+      delete_key: "key".to_string(),
+    };
+
+    // stored by another node, but never loaded here
+    aw!(repositories.room().create_room(Room::new("taken".to_string(), "title".to_string(), host_id,
+                                                  "host".to_string(), None, Utc::now(), "key".to_string())));
+    assert_eq!(aw!(storage.create_room("taken".to_string(), input.clone())), Err(OutputError::RoomNameTaken));
+    assert!(!aw!(storage.rooms.read()).contains_key("taken"));
+    assert!(aw!(repositories.room_user().load_by_room("taken".to_string())).unwrap().is_empty());
+
+    assert!(aw!(storage.create_room("room".to_string(), input)).is_ok());
+    assert!(aw!(storage.rooms.read()).contains_key("room"));
+    let mut members: Vec<Uuid> = aw!(repositories.room_user().load_by_room("room".to_string())).unwrap()
+      .into_iter()
+      .map(|member| member.user_id)
+      .collect();
+    members.sort();
+    let mut expected = vec![host_id, guest_id];
+    expected.sort();
+    assert_eq!(members, expected);
+    assert!(std::iter::from_fn(|| outputs.try_recv().ok())
+      .any(|parcel| matches!(parcel.output, Output::RoomCreated(_))));
+  }
 }
@@ -1,83 +1,309 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use futures::{StreamExt, TryStreamExt};
+use futures::{future, StreamExt, TryStreamExt};
 use log::{error, info};
+use serde::Deserialize;
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::time::Duration;
-use warp::Filter;
-use warp::ws::WebSocket;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time;
+use uuid::Uuid;
+use warp::{Filter, Rejection};
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+use warp::ws::{Message, WebSocket};
 
+use crate::accounts::AccountService;
+use crate::auth::{self, TokenIssuer, TokenVerifier};
 use crate::client::{RoomClient, UserClient};
+use crate::config::Config;
 use crate::room_storage::RoomStorage;
 use crate::hub::HubOptions;
-use crate::proto::InputParcel;
-use crate::domain::repository::RepositoryFactory;
+use crate::presence::PresenceService;
+use crate::domain::user_repository::UserRepository;
+use crate::proto::{InputParcel, LoginInput, OutputError, RegisterInput, RemoveRoomInput, RoomInput};
+use crate::rest::{self, CreateRoomRequest, DeleteRoomRequest, HistoryQuery, PostMessageRequest};
+use crate::domain::repository::Repositories;
+use crate::session::{SessionId, SessionRegistry};
 use crate::user_storage::UserStorage;
 
+/// One warp server mounting the account routes and the feeds routes, the rooms routes or both,
+/// all backed by the same repository set.
+pub struct Server {
+    bind: SocketAddr,
+    account_server: AccountServer,
+    room_server: Option<RoomServer>,
+    user_server: Option<UserServer>,
+}
+
+/// `/api/accounts`: registration, and login handing out the websocket tokens.
+pub struct AccountServer {
+    accounts: Arc<AccountService>,
+}
+
 pub struct UserServer {
-    port: u16,
     user_storage: Arc<UserStorage>,
+    verifier: Arc<dyn TokenVerifier>,
+    heartbeat: Heartbeat,
 }
 
 pub struct RoomServer {
-    port: u16,
     room_storage: Arc<RoomStorage>,
+    user_repository: Arc<dyn UserRepository>,
+    verifier: Arc<dyn TokenVerifier>,
+    heartbeat: Heartbeat,
+}
+
+/// How often connections are pinged, and how long they may stay silent before being closed.
+#[derive(Clone, Copy)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Server {
+    pub fn new(config: &Config,
+               repositories: Repositories,
+               verifier: Arc<dyn TokenVerifier>,
+               issuer: Arc<dyn TokenIssuer>,
+               rooms: bool,
+               feeds: bool) -> Self {
+        let presence = Arc::new(PresenceService::default());
+        Server {
+            bind: config.server.bind,
+            account_server: AccountServer::new(config, &repositories, issuer),
+            room_server: if feeds {
+                Some(RoomServer::new(config, repositories.clone(), Arc::clone(&presence), Arc::clone(&verifier)))
+            } else {
+                None
+            },
+            user_server: if rooms { Some(UserServer::new(config, repositories, presence, verifier)) } else { None },
+        }
+    }
+
+    pub async fn run(&self) {
+        let mut routes = self.account_server.routes();
+        info!("serving /api/accounts on {}", self.bind);
+
+        let (room_sender, room_receiver) = mpsc::unbounded_channel::<InputParcel>();
+        if let Some(room_server) = &self.room_server {
+            routes = routes.or(room_server.routes(room_sender)).unify()
+                .or(room_server.api_routes()).unify().boxed();
+            info!("serving /ws/{{room_id}}/feeds and /api/rooms/{{room_id}} on {}", self.bind);
+        }
+
+        let (user_sender, user_receiver) = mpsc::unbounded_channel::<InputParcel>();
+        if let Some(user_server) = &self.user_server {
+            routes = routes.or(user_server.routes(user_sender)).unify()
+                .or(user_server.api_routes()).unify().boxed();
+            info!("serving /ws/{{user_id}}/rooms and /api/rooms on {}", self.bind);
+        }
+
+        let shutdown = async {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("failed to install Ctrl+C signal handler");
+        };
+
+        let (_, serving) = warp::serve(routes.recover(rest::recover)).bind_with_graceful_shutdown(self.bind, shutdown);
+
+        let running_rooms = async {
+            match &self.room_server {
+                Some(room_server) => room_server.run(room_receiver).await,
+                None => future::pending().await,
+            }
+        };
+
+        let running_users = async {
+            match &self.user_server {
+                Some(user_server) => user_server.run(user_receiver).await,
+                None => future::pending().await,
+            }
+        };
+
+        tokio::select! {
+          _ = serving => {},
+          _ = running_rooms => {},
+          _ = running_users => {},
+        }
+    }
+}
+
+impl AccountServer {
+    const BODY_LIMIT: u64 = 16 * 1024;
+
+    pub fn new(config: &Config, repositories: &Repositories, issuer: Arc<dyn TokenIssuer>) -> Self {
+        let token_ttl = chrono::Duration::seconds(config.auth.token_ttl_secs as i64);
+        AccountServer {
+            accounts: Arc::new(AccountService::new(repositories, issuer, token_ttl)),
+        }
+    }
+
+    /// `POST /api/accounts` registers, `POST /api/accounts/login` logs in; both take JSON.
+    pub fn routes(&self) -> BoxedFilter<(Response,)> {
+        let accounts = self.accounts.clone();
+        let register = warp::path!("api" / "accounts")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(Self::BODY_LIMIT))
+            .and(warp::body::json())
+            .and(warp::any().map(move || accounts.clone()))
+            .and_then(|input: RegisterInput, accounts: Arc<AccountService>| async move {
+                Ok::<_, Rejection>(rest::reply(accounts.register(input).await, StatusCode::CREATED))
+            });
+
+        let accounts = self.accounts.clone();
+        let login = warp::path!("api" / "accounts" / "login")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(Self::BODY_LIMIT))
+            .and(warp::body::json())
+            .and(warp::any().map(move || accounts.clone()))
+            .and_then(|input: LoginInput, accounts: Arc<AccountService>| async move {
+                Ok::<_, Rejection>(rest::reply(accounts.login(input).await, StatusCode::OK))
+            });
+
+        register.or(login).unify().boxed()
+    }
+}
+
+impl Heartbeat {
+    pub fn new(config: &Config) -> Self {
+        Heartbeat {
+            interval: Duration::from_secs(config.heartbeat.interval_secs),
+            timeout: Duration::from_secs(config.heartbeat.timeout_secs),
+        }
+    }
+
+    /// Pings the connection every interval; completes once it has been silent for the timeout.
+    async fn run(self, sessions: &SessionRegistry, session_id: SessionId, sender: &UnboundedSender<Result<Message, warp::Error>>) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            match sessions.last_seen(session_id) {
+                Some(last_seen) if last_seen.elapsed() < self.timeout => {},
+                _ => return,
+            }
+            if sender.send(Ok(Message::ping(Vec::new()))).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// The bearer token of a request, from its `Authorization` header or `token` parameter.
+fn token() -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::query::<TokenQuery>())
+        .map(|authorization: Option<String>, query: TokenQuery| {
+            auth::bearer_token(authorization.as_deref(), query.token.as_deref())
+        })
+}
+
+/// The user an API request's token names; a bad token is replied to, not rejected.
+fn caller(verifier: Arc<dyn TokenVerifier>) -> impl Filter<Extract = (Result<Uuid, OutputError>,), Error = Rejection> + Clone {
+    token().map(move |token| rest::authenticate(verifier.as_ref(), token))
+}
+
+/// Resolves the user an upgrade request authenticates as; `Err` holds the status refusing it.
+fn authenticate(verifier: &dyn TokenVerifier, token: Option<String>) -> Result<Uuid, StatusCode> {
+    auth::authenticate(verifier, token).map_err(|err| {
+        info!("refusing websocket upgrade: {}", err);
+        StatusCode::UNAUTHORIZED
+    })
 }
 
 impl UserServer {
-    pub fn new(port: u16, repo_fact: RepositoryFactory) -> Self {
+    pub fn new(config: &Config, repositories: Repositories, presence: Arc<PresenceService>, verifier: Arc<dyn TokenVerifier>) -> Self {
         UserServer {
-            port,
             user_storage: Arc::new(UserStorage::new(
-                &repo_fact
+                &repositories,
+                presence,
+                config.channels.user_output,
+                config.pages.rooms,
             )),
+            verifier,
+            heartbeat: Heartbeat::new(config),
         }
     }
 
-    pub async fn run(&self) {
-        let (input_sender, input_receiver) = mpsc::unbounded_channel::<InputParcel>();
+    /// `/ws/{user_id}/rooms`, forwarding every input to `input_sender`. Only the user
+    /// the token names may open it.
+    pub fn routes(&self, input_sender: UnboundedSender<InputParcel>) -> BoxedFilter<(Response,)> {
         let user_storage = self.user_storage.clone();
+        let verifier = self.verifier.clone();
+        let heartbeat = self.heartbeat;
 
-        let user = warp::path!("ws"/ String / "rooms")
+        warp::path!("ws"/ String / "rooms")
+            .and(token())
             .and(warp::ws())
             .and(warp::any().map(move || input_sender.clone()))
             .and(warp::any().map(move || user_storage.clone()))
             .map(
-                move |user_id,
+                move |path_user_id: String,
+                      token: Option<String>,
                       ws: warp::ws::Ws,
                       input_sender: UnboundedSender<InputParcel>,
                       storage: Arc<UserStorage>| {
+                    let user_id = match authenticate(verifier.as_ref(), token) {
+                        Ok(user_id) => user_id,
+                        Err(status) => return status.into_response(),
+                    };
+                    if Uuid::parse_str(&path_user_id).ok() != Some(user_id) {
+                        return StatusCode::FORBIDDEN.into_response();
+                    }
+
                     ws.on_upgrade(move |web_socket| async move {
-                        tokio::spawn(Self::process_client(user_id, storage, web_socket, input_sender));
+                        tokio::spawn(Self::process_client(user_id, storage, web_socket, input_sender, heartbeat));
                     })
+                    .into_response()
                 },
-            );
+            )
+            .boxed()
+    }
 
-        let shutdown = async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("failed to install Ctrl+C signal handler");
-        };
+    /// `GET /api/rooms`: the rooms of the user the token names.
+    pub fn api_routes(&self) -> BoxedFilter<(Response,)> {
+        let user_storage = self.user_storage.clone();
 
-        let (_, serving) = warp::serve(user).bind_with_graceful_shutdown(([127, 0, 0, 1], self.port), shutdown);
-        let running_storage = self.user_storage.run(input_receiver);
+        warp::path!("api" / "rooms")
+            .and(warp::get())
+            .and(caller(self.verifier.clone()))
+            .and_then(move |user_id: Result<Uuid, OutputError>| {
+                let user_storage = user_storage.clone();
+                async move {
+                    let rooms = match user_id {
+                        Ok(user_id) => Ok(user_storage.rooms(user_id).await),
+                        Err(error) => Err(error),
+                    };
+                    Ok::<_, Rejection>(rest::reply(rooms, StatusCode::OK))
+                }
+            })
+            .boxed()
+    }
 
-        tokio::select! {
-          _ = serving => {},
-          _ = running_storage => {},
-        }
+    pub async fn run(&self, input_receiver: UnboundedReceiver<InputParcel>) {
+        self.user_storage.run(input_receiver).await
     }
 
     async fn process_client(
-        user_id: String,
+        user_id: Uuid,
         user_storage: Arc<UserStorage>,
         web_socket: WebSocket,
         input_sender: UnboundedSender<InputParcel>,
+        heartbeat: Heartbeat,
     ) {
-        let output_receiver = user_storage.subscribe();
         let (ws_sink, ws_stream) = web_socket.split();
         let user_client = UserClient::new(user_id);
+        let sessions = user_storage.sessions();
+        let (connection, output_receiver) = UserStorage::connect(&user_storage, user_client.id);
+        let session_id = connection.session_id();
+        let ws_stream = ws_stream.inspect(|_| sessions.touch(session_id));
 
         let reading = user_client
             .read_input(ws_stream)
@@ -90,7 +316,7 @@ impl UserServer {
         tokio::spawn(rx.forward(ws_sink));
 
         let writing = user_client
-            .write_output(output_receiver.into_stream())
+            .write_output(output_receiver)
             .try_for_each(|message| async {
                 tx.send(Ok(message)).unwrap();
                 Ok(())
@@ -99,63 +325,177 @@ impl UserServer {
         if let Err(err) = tokio::select! {
           result = reading => result,
           result = writing => result,
+          _ = heartbeat.run(&sessions, session_id, &tx) => Ok(()),
         } {
             error!("Client connection error: {}", err);
         }
+
+        drop(connection);
     }
 }
 
 impl RoomServer {
-  pub fn new(port: u16, repo_fact: RepositoryFactory) -> Self {
-      RoomServer {
-          port,
-          room_storage: Arc::new(RoomStorage::new(&repo_fact)),
+  pub fn new(config: &Config, repositories: Repositories, presence: Arc<PresenceService>, verifier: Arc<dyn TokenVerifier>) -> Self {
+    let hub_options = HubOptions {
+      max_message_body_length: config.max_message_body_length,
+      page_size: config.pages.messages,
+      feed_capacity: config.feed_capacity,
+      alive_interval: Some(Duration::from_secs(config.heartbeat.interval_secs)),
+      ..Default::default()
+    };
+
+    RoomServer {
+      room_storage: Arc::new(RoomStorage::new(&repositories, presence, config.channels.room_output, hub_options,
+                                              Duration::from_secs(config.hub_idle_secs))),
+      user_repository: repositories.user(),
+      verifier,
+      heartbeat: Heartbeat::new(config),
     }
   }
 
-  pub async fn run(&self) {
-    let (input_sender, input_receiver) = mpsc::unbounded_channel::<InputParcel>();
+  /// `/ws/{room_id}/feeds`, forwarding every input to `input_sender` on behalf of the user
+  /// the token names.
+  pub fn routes(&self, input_sender: UnboundedSender<InputParcel>) -> BoxedFilter<(Response,)> {
     let room_storage = self.room_storage.clone();
+    let verifier = self.verifier.clone();
+    let heartbeat = self.heartbeat;
 
-    let storage = warp::path!("ws"/ String / "feeds")
+    warp::path!("ws"/ String / "feeds")
+      .and(token())
       .and(warp::ws())
       .and(warp::any().map(move || input_sender.clone()))
       .and(warp::any().map(move || room_storage.clone()))
       .map(
         move |room_id,
+              token: Option<String>,
               ws: warp::ws::Ws,
               input_sender: UnboundedSender<InputParcel>,
               storage: Arc<RoomStorage>| {
+            let user_id = match authenticate(verifier.as_ref(), token) {
+              Ok(user_id) => user_id,
+              Err(status) => return status.into_response(),
+            };
+
             ws.on_upgrade(move |web_socket| async move {
-              tokio::spawn(Self::process_client(room_id, storage, web_socket, input_sender));
+              tokio::spawn(Self::process_client(room_id, user_id, storage, web_socket, input_sender, heartbeat));
             })
+            .into_response()
           },
-      );
+      )
+      .boxed()
+  }
 
-    let shutdown = async {
-      tokio::signal::ctrl_c()
-        .await
-        .expect("failed to install Ctrl+C signal handler");
-    };
+  /// The REST side of the feeds, acting as the user the token names:
+  /// `POST`/`DELETE /api/rooms/{room_id}` create and delete the room,
+  /// `GET`/`POST /api/rooms/{room_id}/messages` page its history and post to it.
+  /// Posts reach the room's websockets like any other.
+  pub fn api_routes(&self) -> BoxedFilter<(Response,)> {
+    let room = warp::path!("api" / "rooms" / String);
+    let messages = warp::path!("api" / "rooms" / String / "messages");
 
-    let (_, serving) = warp::serve(storage).bind_with_graceful_shutdown(([127, 0, 0, 1], self.port), shutdown);
-    let running_storage = self.room_storage.run(input_receiver);
+    let (storage, users) = (self.room_storage.clone(), self.user_repository.clone());
+    let create = room
+      .and(warp::post())
+      .and(caller(self.verifier.clone()))
+      .and(warp::body::content_length_limit(AccountServer::BODY_LIMIT))
+      .and(warp::body::json())
+      .and_then(move |room_id: String, user_id: Result<Uuid, _>, request: CreateRoomRequest| {
+        let (storage, users) = (storage.clone(), users.clone());
+        async move {
+          let created = match user_id {
+            Ok(host_id) => match users.load_one_user(host_id).await {
+              Some(host) => {
+                let input = RoomInput {
+                  room_title: request.room_title,
+                  host_id,
+                  host_name: host.name,
+                  participants: request.participants,
+                  delete_key: request.delete_key,
+                };
+                storage.create_room(room_id, input).await
+              },
+              None => Err(OutputError::HostUserNotExists),
+            },
+            Err(error) => Err(error),
+          };
+          Ok::<_, Rejection>(rest::reply(created, StatusCode::CREATED))
+        }
+      });
 
-    tokio::select! {
-      _ = serving => {},
-      _ = running_storage => {},
-    }
+    let storage = self.room_storage.clone();
+    let delete = room
+      .and(warp::delete())
+      .and(caller(self.verifier.clone()))
+      .and(warp::body::content_length_limit(AccountServer::BODY_LIMIT))
+      .and(warp::body::json())
+      .and_then(move |room_id: String, user_id: Result<Uuid, _>, request: DeleteRoomRequest| {
+        let storage = storage.clone();
+        async move {
+          let removed = match user_id {
+            Ok(_) => storage.delete_room(RemoveRoomInput { room_id, delete_key: request.delete_key }).await,
+            Err(error) => Err(error),
+          };
+          Ok::<_, Rejection>(rest::reply(removed, StatusCode::OK))
+        }
+      });
+
+    let storage = self.room_storage.clone();
+    let history = messages
+      .and(warp::get())
+      .and(caller(self.verifier.clone()))
+      .and(warp::query::<HistoryQuery>())
+      .and_then(move |room_id: String, user_id: Result<Uuid, _>, query: HistoryQuery| {
+        let storage = storage.clone();
+        async move {
+          let loaded = match user_id {
+            Ok(user_id) => storage.load_history(&room_id, user_id, query.before.as_deref(), query.limit).await,
+            Err(error) => Err(error),
+          };
+          Ok::<_, Rejection>(rest::reply(loaded, StatusCode::OK))
+        }
+      });
+
+    let storage = self.room_storage.clone();
+    let post = messages
+      .and(warp::post())
+      .and(caller(self.verifier.clone()))
+      .and(warp::body::content_length_limit(AccountServer::BODY_LIMIT))
+      .and(warp::body::json())
+      .and_then(move |room_id: String, user_id: Result<Uuid, _>, request: PostMessageRequest| {
+        let storage = storage.clone();
+        async move {
+          let posted = match user_id {
+            Ok(user_id) => storage.post_message(&room_id, user_id, &request.body).await,
+            Err(error) => Err(error),
+          };
+          Ok::<_, Rejection>(rest::reply(posted, StatusCode::CREATED))
+        }
+      });
+
+    create.or(delete).unify()
+      .or(history).unify()
+      .or(post).unify()
+      .boxed()
+  }
+
+  pub async fn run(&self, input_receiver: UnboundedReceiver<InputParcel>) {
+    self.room_storage.run(input_receiver).await
   }
 
   async fn process_client(
     room_id: String,
+    user_id: Uuid,
     room_storage: Arc<RoomStorage>,
     web_socket: WebSocket,
     input_sender: UnboundedSender<InputParcel>,
+    heartbeat: Heartbeat,
   ) {
-    let output_receiver = room_storage.subscribe();
     let (ws_sink, ws_stream) = web_socket.split();
-    let room_client = RoomClient::new(room_id);
+    let room_client = RoomClient::new(room_id, user_id);
+    let sessions = room_storage.sessions();
+    let (connection, output_receiver) = RoomStorage::connect(&room_storage, &room_client.room_id, room_client.id);
+    let session_id = connection.session_id();
+    let ws_stream = ws_stream.inspect(|_| sessions.touch(session_id));
 
     let reading = room_client
       .read_input(ws_stream)
@@ -166,19 +506,29 @@ impl RoomServer {
 
     let (tx, rx) = mpsc::unbounded_channel();
     tokio::spawn(rx.forward(ws_sink));
-    
+
     let writing = room_client
-      .write_output(output_receiver.into_stream())
+      .write_output(output_receiver)
       .try_for_each(|message| async {
         tx.send(Ok(message)).unwrap();
         Ok(())
       });
 
-    if let Err(err) = tokio::select! {
-      result = reading => result,
-      result = writing => result,
+    let reaped = match tokio::select! {
+      result = reading => result.map(|_| false),
+      result = writing => result.map(|_| false),
+      _ = heartbeat.run(&sessions, session_id, &tx) => Ok(true),
     } {
-      error!("Client connection error: {}", err);
+      Ok(reaped) => reaped,
+      Err(err) => {
+        error!("Client connection error: {}", err);
+        false
+      }
+    };
+
+    if reaped {
+      info!("closing silent connection in room {}", room_client.room_id);
     }
+    drop(connection);
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Instant;
+use log::warn;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use uuid::Uuid;
+
+use crate::proto::OutputParcel;
+
+pub type SessionId = u64;
+
+/// Routes output parcels to the connections they concern.
+///
+/// Every websocket connection registers a session under the room it is attached to (an empty
+/// room for the per-user socket) and the client its token authenticated. A parcel with a nil
+/// `client_id` goes to every session of its room, any other parcel only to the sessions of
+/// that client in that room. Each session has its own bounded channel: a connection that stops
+/// reading fills its channel and is dropped without holding up anybody else.
+pub struct SessionRegistry {
+  channel_size: usize,
+  sessions: Mutex<Sessions>,
+}
+
+#[derive(Default)]
+struct Sessions {
+  next_id: SessionId,
+  sessions: HashMap<SessionId, Session>,
+  by_room: HashMap<String, HashSet<SessionId>>,
+  by_client: HashMap<Uuid, HashSet<SessionId>>,
+}
+
+struct Session {
+  room_id: String,
+  client_id: Uuid,
+  sender: mpsc::Sender<OutputParcel>,
+  last_seen: Instant,
+}
+
+impl SessionRegistry {
+  pub fn new(channel_size: usize) -> Self {
+    SessionRegistry {
+      channel_size,
+      sessions: Default::default(),
+    }
+  }
+
+  pub fn register(&self, room_id: &str, client_id: Uuid) -> (SessionId, mpsc::Receiver<OutputParcel>) {
+    let (sender, receiver) = mpsc::channel(self.channel_size);
+    let mut sessions = self.sessions.lock().unwrap();
+
+    sessions.next_id += 1;
+    let session_id = sessions.next_id;
+    sessions.insert(session_id, Session { room_id: room_id.to_string(), client_id, sender, last_seen: Instant::now() });
+    (session_id, receiver)
+  }
+
+  /// Forgets the session, returning the client it spoke for.
+  pub fn unregister(&self, session_id: SessionId) -> Option<Uuid> {
+    self.sessions.lock().unwrap().remove(session_id).map(|session| session.client_id)
+  }
+
+  /// Records that the connection behind the session is still talking to us.
+  pub fn touch(&self, session_id: SessionId) {
+    if let Some(session) = self.sessions.lock().unwrap().sessions.get_mut(&session_id) {
+      session.last_seen = Instant::now();
+    }
+  }
+
+  pub fn last_seen(&self, session_id: SessionId) -> Option<Instant> {
+    self.sessions.lock().unwrap().sessions.get(&session_id).map(|session| session.last_seen)
+  }
+
+  /// Rooms with at least one session.
+  pub fn rooms(&self) -> Vec<String> {
+    self.sessions.lock().unwrap().by_room.keys().cloned().collect()
+  }
+
+  pub fn send(&self, output_parcel: OutputParcel) {
+    let mut sessions = self.sessions.lock().unwrap();
+
+    let targets: Vec<SessionId> = if output_parcel.client_id.is_nil() {
+      sessions.by_room.get(&output_parcel.room_id)
+        .map(|ids| ids.iter().copied().collect())
+        .unwrap_or_default()
+    } else {
+      sessions.by_client.get(&output_parcel.client_id)
+        .map(|ids| ids.iter()
+          .copied()
+          .filter(|id| sessions.sessions[id].room_id == output_parcel.room_id)
+          .collect())
+        .unwrap_or_default()
+    };
+
+    let mut dropped = vec!();
+    for session_id in targets {
+      let session = sessions.sessions.get_mut(&session_id).unwrap();
+      match session.sender.try_send(output_parcel.clone()) {
+        Ok(_) => {},
+        Err(TrySendError::Full(_)) => {
+          warn!("session {} in room {:?} is not reading, dropping it", session_id, session.room_id);
+          dropped.push(session_id);
+        },
+        Err(TrySendError::Closed(_)) => dropped.push(session_id),
+      }
+    }
+
+    for session_id in dropped {
+      sessions.remove(session_id);
+    }
+  }
+
+  /// Clients with at least one session in the room.
+  pub fn clients(&self, room_id: &str) -> HashSet<Uuid> {
+    let sessions = self.sessions.lock().unwrap();
+    sessions.by_room.get(room_id)
+      .map(|ids| ids.iter().map(|id| sessions.sessions[id].client_id).collect())
+      .unwrap_or_default()
+  }
+
+  pub fn session_count(&self, room_id: &str) -> usize {
+    self.sessions.lock().unwrap().by_room.get(room_id).map_or(0, HashSet::len)
+  }
+
+  /// Connections the client still has to the room.
+  pub fn client_session_count(&self, room_id: &str, client_id: Uuid) -> usize {
+    let sessions = self.sessions.lock().unwrap();
+    sessions.by_client.get(&client_id)
+      .map_or(0, |ids| ids.iter().filter(|id| sessions.sessions[id].room_id == room_id).count())
+  }
+}
+
+impl Sessions {
+  fn insert(&mut self, session_id: SessionId, session: Session) {
+    self.by_room.entry(session.room_id.clone()).or_default().insert(session_id);
+    if !session.client_id.is_nil() {
+      self.by_client.entry(session.client_id).or_default().insert(session_id);
+    }
+    self.sessions.insert(session_id, session);
+  }
+
+  fn remove(&mut self, session_id: SessionId) -> Option<Session> {
+    let session = self.sessions.remove(&session_id)?;
+    Self::remove_index(&mut self.by_room, &session.room_id, session_id);
+    Self::remove_index(&mut self.by_client, &session.client_id, session_id);
+    Some(session)
+  }
+
+  fn remove_index<K: Eq + std::hash::Hash>(index: &mut HashMap<K, HashSet<SessionId>>, key: &K, session_id: SessionId) {
+    if let Some(ids) = index.get_mut(key) {
+      ids.remove(&session_id);
+      if ids.is_empty() {
+        index.remove(key);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::proto::Output;
+
+  #[test]
+  fn test001_routes_by_room_and_client() {
+    let registry = SessionRegistry::new(4);
+    let alice = Uuid::new_v4();
+    let bob = Uuid::new_v4();
+
+    let (_, mut alice_room) = registry.register("room", alice);
+    let (_, mut bob_room) = registry.register("room", bob);
+    let (_, mut other_room) = registry.register("other", Uuid::nil());
+
+    registry.send(OutputParcel::new("room".to_string(), Uuid::nil(), Output::Pong));
+    registry.send(OutputParcel::new("room".to_string(), bob, Output::Alive));
+
+    assert_eq!(alice_room.try_recv().unwrap().output, Output::Pong);
+    assert!(alice_room.try_recv().is_err());
+    assert_eq!(bob_room.try_recv().unwrap().output, Output::Pong);
+    assert_eq!(bob_room.try_recv().unwrap().output, Output::Alive);
+    assert!(other_room.try_recv().is_err());
+  }
+
+  #[test]
+  fn test002_slow_session_is_dropped_alone() {
+    let registry = SessionRegistry::new(1);
+    let (_, mut fast) = registry.register("room", Uuid::nil());
+    let (_, _slow) = registry.register("room", Uuid::nil());
+
+    registry.send(OutputParcel::new("room".to_string(), Uuid::nil(), Output::Pong));
+    fast.try_recv().unwrap();
+    registry.send(OutputParcel::new("room".to_string(), Uuid::nil(), Output::Pong));
+
+    assert_eq!(registry.session_count("room"), 1);
+    assert!(fast.try_recv().is_ok());
+  }
+
+  #[test]
+  fn test003_unregister_cleans_indexes() {
+    let registry = SessionRegistry::new(1);
+    let (session_id, _receiver) = registry.register("room", Uuid::new_v4());
+    registry.unregister(session_id);
+
+    assert_eq!(registry.session_count("room"), 0);
+    assert!(registry.sessions.lock().unwrap().by_client.is_empty());
+  }
+
+  #[test]
+  fn test004_touch_keeps_client_and_rooms() {
+    let registry = SessionRegistry::new(1);
+    let client_id = Uuid::new_v4();
+    let (session_id, _receiver) = registry.register("room", client_id);
+    let registered = registry.last_seen(session_id).unwrap();
+
+    registry.touch(session_id);
+    assert!(registry.last_seen(session_id).unwrap() >= registered);
+    assert_eq!(registry.rooms(), vec!["room".to_string()]);
+    assert_eq!(registry.clients("room").into_iter().collect::<Vec<Uuid>>(), vec![client_id]);
+
+    assert_eq!(registry.unregister(session_id), Some(client_id));
+    assert!(registry.last_seen(session_id).is_none());
+    assert!(registry.rooms().is_empty());
+  }
+}
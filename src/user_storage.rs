@@ -1,45 +1,75 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::broadcast;
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::RwLock;
+use tokio::sync::broadcast::{self, RecvError};
+use tokio::sync::mpsc::{Receiver, UnboundedReceiver};
 use futures::StreamExt;
+use log::warn;
 use uuid::Uuid;
 
-use crate::proto::{OutputParcel, InputParcel, Input, RoomOutput, Output, RoomsLoadedOutput};
+use crate::proto::{OutputParcel, InputParcel, Input, RoomOutput, Output, OutputError, RoomsLoadedOutput, PresenceOutput, PresenceStatus, SetStatusInput};
+use crate::domain::message_repository::MessageRepository;
 use crate::domain::room_user_repository::RoomUserRepository;
-use crate::domain::repository::RepositoryFactory;
-use crate::utils::AppUtils;
+use crate::domain::repository::Repositories;
+use crate::model::message::Message;
+use crate::model::room_user::RoomUser;
+use crate::presence::PresenceService;
+use crate::session::{SessionId, SessionRegistry};
 
-const OUTPUT_CHANNEL_SIZE: usize = 16;
+/// Unread counts stop here; clients show it as "100+".
+pub const MAX_UNREAD_COUNT: usize = 100;
 
 pub struct UserStorage {
-    output_sender: broadcast::Sender<OutputParcel>,
-    room_user_repo: Arc<RoomUserRepository>
+    sessions: Arc<SessionRegistry>,
+    presence: Arc<PresenceService>,
+    room_user_repo: Arc<dyn RoomUserRepository>,
+    msg_repo: Arc<dyn MessageRepository>,
+    page_size: i32,
+    // rooms of the users whose presence changed, loaded at their first change and dropped when
+    // they go offline; rooms joined meanwhile count from their next connect
+    memberships: RwLock<HashMap<Uuid, Arc<HashSet<String>>>>,
 }
 
-impl UserStorage {
-    pub fn new(repo_fact: &RepositoryFactory) -> Self {
-        let (output_sender, _) = broadcast::channel(OUTPUT_CHANNEL_SIZE);
-
-        let room_user_repo = match AppUtils::downcast_arc::<RoomUserRepository>(
-            repo_fact.get_repository("ROOM_USERS")) {
-            Ok(repo) => repo,
-            Err(_) => panic!("can't find repository")
-        };
+/// A user's room list socket; dropping it unregisters its session and its presence.
+pub struct UserConnection {
+    storage: Arc<UserStorage>,
+    user_id: Uuid,
+    session_id: SessionId,
+}
 
+impl UserStorage {
+    pub fn new(repositories: &Repositories, presence: Arc<PresenceService>, output_channel_size: usize, page_size: i32) -> Self {
         UserStorage {
-            output_sender,
-            room_user_repo,
+            sessions: Arc::new(SessionRegistry::new(output_channel_size)),
+            presence,
+            room_user_repo: repositories.room_user(),
+            msg_repo: repositories.message(),
+            page_size,
+            memberships: Default::default(),
         }
     }
 
-    pub fn subscribe(&self) -> broadcast::Receiver<OutputParcel> {
-        self.output_sender.subscribe()
+    pub fn sessions(&self) -> Arc<SessionRegistry> {
+        Arc::clone(&self.sessions)
+    }
+
+    pub fn connect(storage: &Arc<UserStorage>, user_id: Uuid) -> (UserConnection, Receiver<OutputParcel>) {
+        let (session_id, receiver) = storage.sessions.register("", user_id);
+        storage.presence.connect(user_id);
+        let connection = UserConnection {
+            storage: Arc::clone(storage),
+            user_id,
+            session_id,
+        };
+        (connection, receiver)
     }
 
     pub async fn run(&self, receiver: UnboundedReceiver<InputParcel>) {
+        let relaying = self.relay_presence(self.presence.subscribe());
         let processing = receiver.for_each(|input_parcel| self.process(input_parcel));
 
         tokio::select! {
+          _ = relaying => {},
           _ = processing => {},
         }
     }
@@ -48,39 +78,233 @@ impl UserStorage {
         match input_parcel.input {
             Input::Ping => self.send_pong(input_parcel.client_id),
             Input::LoadRooms(input) => self.load_rooms(input.user_id).await,
-            _ => unimplemented!()
+            Input::SetStatus(input) => self.set_status(input_parcel.client_id, input),
+            _ => self.send_error(input_parcel.client_id, OutputError::UnsupportedInput),
         }
     }
 
     fn send_pong(&self, user_id: Uuid) {
-        self.output_sender
-            .send(OutputParcel::new("".to_string(), user_id, Output::Pong))
-            .unwrap();
+        self.sessions
+            .send(OutputParcel::new("".to_string(), user_id, Output::Pong));
+    }
+
+    fn set_status(&self, user_id: Uuid, input: SetStatusInput) {
+        if let Err(error) = self.presence.set_status(user_id, input.status, input.text) {
+            self.send_error(user_id, error);
+        }
+    }
+
+    fn send_error(&self, user_id: Uuid, error: OutputError) {
+        self.sessions.send(OutputParcel::new("".to_string(), user_id, Output::Error(error)));
+    }
+
+    async fn relay_presence(&self, mut changes: broadcast::Receiver<PresenceOutput>) {
+        loop {
+            match changes.recv().await {
+                Ok(presence) => self.on_presence_changed(presence).await,
+                Err(RecvError::Lagged(skipped)) => warn!("dropped {} presence changes", skipped),
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Pushes `presence-changed` to the room lists of everyone sharing a room with the user,
+    /// matched on the cached memberships of the connected lists.
+    async fn on_presence_changed(&self, presence: PresenceOutput) {
+        let user_id = presence.user_id;
+        let connected = self.sessions.clients("");
+        if !connected.is_empty() {
+            let rooms = self.memberships(user_id).await;
+            for member in connected {
+                if !self.memberships(member).await.is_disjoint(&rooms) {
+                    self.sessions.send(OutputParcel::new("".to_string(), member, Output::PresenceChanged(presence.clone())));
+                }
+            }
+        }
+
+        if presence.status == PresenceStatus::Offline {
+            self.memberships.write().await.remove(&user_id);
+        }
+    }
+
+    async fn memberships(&self, user_id: Uuid) -> Arc<HashSet<String>> {
+        if let Some(rooms) = self.memberships.read().await.get(&user_id) {
+            return Arc::clone(rooms);
+        }
+
+        let rooms: Arc<HashSet<String>> = Arc::new(self.all_rooms(user_id).await
+            .into_iter()
+            .map(|room| room.room_id)
+            .collect());
+        self.memberships.write().await.insert(user_id, Arc::clone(&rooms));
+        rooms
+    }
+
+    /// Every room of the user, page after page.
+    async fn all_rooms(&self, user_id: Uuid) -> Vec<RoomUser> {
+        let mut rooms = vec![];
+        let mut page = 1;
+        loop {
+            let loaded = self.room_user_repo.load_by_userid(user_id, page, self.page_size).await.unwrap_or_default();
+            let last_page = loaded.len() < self.page_size as usize;
+            rooms.extend(loaded);
+            if last_page {
+                return rooms;
+            }
+            page += 1;
+        }
     }
 
     async fn load_rooms(&self, user_id: Uuid) {
-        let result = self.room_user_repo.load_by_userid(user_id, 1, 10).await
-                    .as_ref()
-                    .map(|rooms|
-                        rooms
-                            .iter()
-                            .map(|room| {
-                                RoomOutput::new(
-                                    room.room_id.clone(),
-                                    room.room_title.clone(),
-                                    room.user_id,
-                                    room.create_at
-                                )
-                    })
-                    .collect());
-
-        self.output_sender
-            .send(
-                OutputParcel::new(
-                    "".to_string(),
-                    user_id,
-                    Output::RoomsLoaded(RoomsLoadedOutput::new(result.unwrap())))
-            )
-            .unwrap();
-    }
-}
\ No newline at end of file
+        let loaded = self.rooms(user_id).await;
+        self.sessions.send(OutputParcel::new("".to_string(), user_id, Output::RoomsLoaded(loaded)));
+    }
+
+    /// The first page of the user's rooms with their unread counts.
+    pub async fn rooms(&self, user_id: Uuid) -> RoomsLoadedOutput {
+        let rooms = self.room_user_repo.load_by_userid(user_id, 1, self.page_size).await.unwrap_or_default();
+
+        let mut result = Vec::with_capacity(rooms.len());
+        for room in rooms {
+            let unread_count = self.unread_count(&room).await;
+            result.push(
+                RoomOutput::new(
+                    room.room_id,
+                    room.room_title,
+                    room.user_id,
+                    room.create_at,
+                    unread_count
+                )
+            );
+        }
+        RoomsLoadedOutput::new(result)
+    }
+
+    /// Counts the messages others posted after the member's read marker, newest first.
+    async fn unread_count(&self, room: &RoomUser) -> usize {
+        let last_read = room.last_read.map(|id| Message::id_order(&id));
+        self.msg_repo.load_messages_by_room(&room.room_id, None, MAX_UNREAD_COUNT as i32).await
+            .unwrap_or_default()
+            .iter()
+            .take_while(|msg| last_read.iter().all(|last_read| Message::id_order(&msg.id) > *last_read))
+            .filter(|msg| msg.from.id != room.user_id)
+            .count()
+    }
+}
+
+impl UserConnection {
+    pub fn session_id(&self) -> SessionId {
+        self.session_id
+    }
+}
+
+impl Drop for UserConnection {
+    fn drop(&mut self) {
+        self.storage.sessions.unregister(self.session_id);
+        self.storage.presence.disconnect(self.user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::model::user::User;
+    use crate::proto::PostInput;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test001_presence_reaches_room_mates_lists() {
+        let repositories = Repositories::in_memory();
+        // alice shares a room with each mate, more rooms than fit a page
+        let storage = Arc::new(UserStorage::new(&repositories, Default::default(), 16, 2));
+        let alice = Uuid::new_v4();
+        let mates = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let stranger = Uuid::new_v4();
+        for (index, mate) in mates.iter().enumerate() {
+            for user_id in &[alice, *mate] {
+                aw!(repositories.room_user().create_room_users(
+                    RoomUser::new(format!("room{}", index), "title".to_string(), *user_id, "name".to_string(), Utc::now())));
+            }
+        }
+
+        let mut changes = storage.presence.subscribe();
+        let mut mate_outputs: Vec<_> = mates.iter().map(|mate| UserStorage::connect(&storage, *mate)).collect();
+        let (_stranger_connection, mut stranger_outputs) = UserStorage::connect(&storage, stranger);
+        let (alice_connection, _alice_outputs) = UserStorage::connect(&storage, alice);
+        aw!(storage.process(InputParcel::new(alice, "".to_string(), Input::SetStatus(
+            SetStatusInput { client_id: alice, status: PresenceStatus::Away, text: None }))));
+        drop(alice_connection);
+
+        while let Ok(presence) = changes.try_recv() {
+            aw!(storage.on_presence_changed(presence));
+        }
+
+        for (_, outputs) in mate_outputs.iter_mut() {
+            let statuses: Vec<PresenceStatus> = std::iter::from_fn(|| outputs.try_recv().ok())
+                .filter_map(|parcel| match parcel.output {
+                    Output::PresenceChanged(presence) if presence.user_id == alice => Some(presence.status),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(statuses, vec![PresenceStatus::Online, PresenceStatus::Away, PresenceStatus::Offline]);
+        }
+        assert!(stranger_outputs.try_recv().is_err());
+
+        // only the users still online keep their rooms cached
+        let cached = aw!(storage.memberships.read());
+        assert!(!cached.contains_key(&alice));
+        assert_eq!(cached[&mates[0]].len(), 1);
+    }
+
+    #[test]
+    fn test002_unread_counts_start_after_read_marker() {
+        let repositories = Repositories::in_memory();
+        let storage = Arc::new(UserStorage::new(&repositories, Default::default(), 16, 10));
+        let alice = User::new(Uuid::new_v4(), "alice");
+        let bob = User::new(Uuid::new_v4(), "bob");
+        for user in &[&alice, &bob] {
+            aw!(repositories.room_user().create_room_users(
+                RoomUser::new("room".to_string(), "title".to_string(), user.id, user.name.clone(), Utc::now())));
+        }
+        let mut read = None;
+        for (from, body) in &[(&alice, "first"), (&bob, "mine"), (&alice, "second"), (&alice, "third")] {
+            let message = aw!(repositories.message().add_new_message("room", Message::new((*from).clone(), "room", body))).unwrap();
+            if *body == "mine" {
+                read = Some(message.id);
+            }
+        }
+
+        let (_connection, mut outputs) = UserStorage::connect(&storage, bob.id);
+        let unread_count = |outputs: &mut Receiver<OutputParcel>| match outputs.try_recv().unwrap().output {
+            Output::RoomsLoaded(loaded) => loaded.rooms[0].unread_count,
+            output => panic!("unexpected {:?}", output),
+        };
+
+        aw!(storage.load_rooms(bob.id));
+        assert_eq!(unread_count(&mut outputs), 3);
+
+        aw!(repositories.room_user().advance_last_read("room".to_string(), bob.id, read.unwrap()));
+        aw!(storage.load_rooms(bob.id));
+        assert_eq!(unread_count(&mut outputs), 2);
+    }
+
+    #[test]
+    fn test003_room_inputs_are_refused_not_fatal() {
+        let storage = Arc::new(UserStorage::new(&Repositories::in_memory(), Default::default(), 16, 10));
+        let alice = Uuid::new_v4();
+        let (_connection, mut outputs) = UserStorage::connect(&storage, alice);
+
+        aw!(storage.process(InputParcel::new(alice, "".to_string(), Input::PostMessage(
+            PostInput { client_id: alice, body: "hello".to_string() }))));
+        assert_eq!(outputs.try_recv().unwrap().output, Output::Error(OutputError::UnsupportedInput));
+
+        aw!(storage.process(InputParcel::new(alice, "".to_string(), Input::Ping)));
+        assert_eq!(outputs.try_recv().unwrap().output, Output::Pong);
+    }
+}